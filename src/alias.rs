@@ -0,0 +1,214 @@
+//! Aliasing handles: an `Rcn<U>` to a part of the value of another box, sharing that box's counts.
+//!
+//! An aliasing handle points to an alias box instead of an `RcnBox<U>`, with the lowest address bit set to
+//! tell them apart; boxes are at least word-aligned, so the bit is free. The alias box records the header of
+//! the parent box, whose counts every handle to it reads and updates, and the address of the aliased value.
+//! The alias box itself is freed with the last handle pointing to it.
+
+use std::cell::Cell;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use crate::drops;
+use crate::RcnBox;
+
+const TAG: usize = 1;
+
+pub(crate) struct AliasBox<U: ?Sized> {
+    /// The parent box, with its type erased. Fat pointers take two words.
+    parent: [usize; 2],
+    /// Header of the parent box, holding the counts.
+    header: *mut RcnBox<()>,
+    /// Releases the parent when its strong count reaches zero.
+    release: unsafe fn([usize; 2]),
+    /// Frees the parent once no reference to it is left.
+    deallocate: unsafe fn([usize; 2]),
+    /// Handles pointing to this alias box.
+    handles: Cell<usize>,
+    target: NonNull<U>,
+}
+
+unsafe fn release<T: ?Sized>(raw: [usize; 2]) {
+    drops::release(ptr::read(raw.as_ptr() as *const NonNull<RcnBox<T>>));
+}
+
+unsafe fn deallocate<T: ?Sized>(raw: [usize; 2]) {
+    RcnBox::deallocate(ptr::read(raw.as_ptr() as *const NonNull<RcnBox<T>>).as_ptr());
+}
+
+fn address<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> usize {
+    ptr.as_ptr() as *mut () as usize
+}
+
+/// Returns `true` if `ptr` points to an alias box.
+#[inline(always)]
+pub(crate) fn is_alias<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> bool {
+    let address = address(ptr);
+    address & TAG != 0 && address != usize::MAX
+}
+
+#[inline]
+fn alias_box<U: ?Sized>(ptr: NonNull<RcnBox<U>>) -> *mut AliasBox<U> {
+    ptr::with_exposed_provenance_mut(address(ptr) & !TAG)
+}
+
+/// Returns the header holding the counts of the box behind `ptr`, which must not be the none sentinel.
+#[inline(always)]
+pub(crate) fn header<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> *mut RcnBox<()> {
+    if is_alias(ptr) {
+        unsafe { (*alias_box(ptr)).header }
+    } else {
+        ptr.as_ptr() as *mut RcnBox<()>
+    }
+}
+
+/// Returns the value behind `ptr`, which must not be the none sentinel.
+#[inline(always)]
+pub(crate) fn value<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> *mut T {
+    if is_alias(ptr) {
+        unsafe { (*alias_box(ptr)).target.as_ptr() }
+    } else {
+        unsafe { ptr::addr_of_mut!((*ptr.as_ptr()).value) }
+    }
+}
+
+/// Creates an alias box for `target`, a value inside the box behind `parent`, and returns a pointer to it
+/// with no handle counted yet. The caller adds the strong reference of the new handle to the parent.
+pub(crate) fn create<T: ?Sized, U: ?Sized>(parent: NonNull<RcnBox<T>>, target: &mut U) -> NonNull<RcnBox<U>> {
+    let target = NonNull::from(target);
+    let alias = if is_alias(parent) {
+        let outer = unsafe { &*alias_box(parent) };
+        AliasBox {
+            parent: outer.parent,
+            header: outer.header,
+            release: outer.release,
+            deallocate: outer.deallocate,
+            handles: Cell::new(0),
+            target,
+        }
+    } else {
+        assert!(mem::size_of::<NonNull<RcnBox<T>>>() <= mem::size_of::<[usize; 2]>());
+        let mut raw = [0; 2];
+        unsafe { ptr::write(raw.as_mut_ptr() as *mut NonNull<RcnBox<T>>, parent) };
+        AliasBox {
+            parent: raw,
+            header: parent.as_ptr() as *mut RcnBox<()>,
+            release: release::<T>,
+            deallocate: deallocate::<T>,
+            handles: Cell::new(0),
+            target,
+        }
+    };
+    let address = Box::into_raw(Box::new(alias)).expose_provenance();
+    // Only the address is used to find the alias box again; the metadata of `U` rides along for the
+    // `Rcn<U>` that holds the pointer.
+    let tagged = (target.as_ptr() as *mut RcnBox<U>).with_addr(address | TAG);
+    unsafe { NonNull::new_unchecked(tagged) }
+}
+
+/// Counts a new handle to the alias box behind `ptr`.
+#[inline]
+pub(crate) fn add_handle<U: ?Sized>(ptr: NonNull<RcnBox<U>>) {
+    let alias = unsafe { &*alias_box(ptr) };
+    alias.handles.set(alias.handles.get() + 1);
+}
+
+/// Forgets a handle to the alias box behind `ptr`, freeing the alias box with the last one.
+pub(crate) unsafe fn remove_handle<U: ?Sized>(ptr: NonNull<RcnBox<U>>) {
+    let alias = alias_box(ptr);
+    let handles = (*alias).handles.get() - 1;
+    (*alias).handles.set(handles);
+    if handles == 0 {
+        drop(Box::from_raw(alias));
+    }
+}
+
+/// Releases the parent of the alias box behind `ptr`, whose strong count just reached zero.
+pub(crate) unsafe fn release_parent<U: ?Sized>(ptr: NonNull<RcnBox<U>>) {
+    let alias = alias_box(ptr);
+    ((*alias).release)((*alias).parent);
+}
+
+/// Frees the parent of the alias box behind `ptr`, which has no references left.
+pub(crate) unsafe fn deallocate_parent<U: ?Sized>(ptr: NonNull<RcnBox<U>>) {
+    let alias = alias_box(ptr);
+    ((*alias).deallocate)((*alias).parent);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rcn, RcnScope};
+    use std::panic::{self, AssertUnwindSafe};
+
+    struct Document {
+        title: String,
+        pages: Vec<u32>,
+    }
+
+    fn document() -> Rcn<Document> {
+        Rcn::new(Document { title: String::from("draft"), pages: vec![1, 2, 3] })
+    }
+
+    fn count_pages(pages: Rcn<Vec<u32>>) -> usize {
+        pages.len()
+    }
+
+    #[test]
+    fn aliases_share_the_parent_counts() {
+        let mut doc = document();
+        let title = Rcn::alias(&mut doc, |d| &mut d.title);
+        let pages = Rcn::alias(&mut doc, |d| &mut d.pages);
+        assert_eq!(doc.strong_count(), 3);
+        assert_eq!(title.strong_count(), 3);
+        assert_eq!(&*title, "draft");
+
+        let weak = pages.downgrade();
+        assert_eq!(doc.weak_count(), 1);
+        assert_eq!(count_pages(pages.share()), 3);
+        drop((doc, title));
+        assert_eq!(pages.strong_count(), 1);
+        drop(pages);
+        assert!(weak.upgrade().is_none());
+        assert!(weak.is_none());
+    }
+
+    #[test]
+    fn unsized_and_nested_aliases() {
+        let mut doc = document();
+        let mut title: Rcn<str> = Rcn::alias(&mut doc, |d| d.title.as_mut_str());
+        let first: Rcn<[u32]> = Rcn::alias(&mut Rcn::alias(&mut doc, |d| &mut d.pages), |p| &mut p[..1]);
+        drop(doc);
+        assert_eq!(&*title, "draft");
+        assert_eq!(*first, [1]);
+        assert_eq!(title.strong_count(), 2);
+        title.make_ascii_uppercase();
+        assert_eq!(&*first.share(), &[1]);
+
+        let upgraded = title.downgrade().upgrade().unwrap();
+        assert!(Rcn::ptr_eq(&upgraded, &title));
+        assert_eq!(&*upgraded, "DRAFT");
+    }
+
+    #[test]
+    fn take_and_unwrap_refuse_aliases() {
+        let mut doc = document();
+        let mut pages = Rcn::alias(&mut doc, |d| &mut d.pages);
+        drop(doc);
+        assert!(pages.is_unique());
+        assert_eq!(pages.take(), None);
+        let pages = Rcn::try_unwrap(pages).unwrap_err();
+        assert_eq!(*pages, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn aliases_of_scoped_boxes() {
+        let mut alias = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let scope = RcnScope::new();
+            let mut doc = scope.alloc(document().take().unwrap());
+            alias = Some(Rcn::alias(&mut doc, |d| &mut d.title));
+        }));
+        assert!(result.is_err());
+        assert!(alias.as_ref().unwrap().is_none());
+    }
+}
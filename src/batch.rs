@@ -0,0 +1,83 @@
+//! Batch allocation: many boxes in one contiguous block.
+
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::Cell;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use crate::region::{self, Owner};
+use crate::{Rcn, RcnBox};
+
+/// A block of boxes, freed when the last of them is released.
+struct Block {
+    mem: *mut u8,
+    layout: Layout,
+    live: Cell<usize>,
+}
+
+impl Owner for Block {
+    unsafe fn release(&self, _ptr: *mut u8, _layout: Layout) {
+        let live = self.live.get() - 1;
+        self.live.set(live);
+        if live == 0 {
+            region::unclaim(self.mem);
+            dealloc(self.mem, self.layout);
+        }
+    }
+}
+
+/// Allocates one box per value in a single block.
+#[track_caller]
+pub(crate) fn allocate<T>(values: Vec<T>) -> Vec<Rcn<T>> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let layout = Layout::array::<RcnBox<T>>(values.len()).expect("Rcn batch too large");
+    let mem = unsafe { alloc(layout) };
+    if mem.is_null() {
+        handle_alloc_error(layout);
+    }
+    let block = Rc::new(Block { mem, layout, live: Cell::new(values.len()) });
+    region::claim(mem, layout.size(), block);
+    let boxes = mem as *mut RcnBox<T>;
+    values.into_iter().enumerate().map(|(i, value)| unsafe {
+        let ptr = boxes.add(i);
+        ptr::write(ptr, RcnBox::owned(value));
+        RcnBox::register(ptr);
+        Rcn::from_inner(NonNull::new_unchecked(ptr))
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rcn, RcnBox};
+    use std::mem;
+
+    #[test]
+    fn members_are_independent() {
+        let mut batch = Rcn::new_batch((0..4).map(|i| i.to_string()));
+        assert_eq!(batch.len(), 4);
+        let first = &*batch[0] as *const String as usize;
+        let second = &*batch[1] as *const String as usize;
+        assert_eq!(second - first, mem::size_of::<RcnBox<String>>());
+
+        let shared = batch[1].share();
+        let weak = batch[2].downgrade();
+        assert_eq!(batch[0].take(), Some(String::from("0")));
+        assert_eq!(batch[1].strong_count(), 2);
+        assert_eq!(batch[2].weak_count(), 1);
+
+        let last = batch.pop().unwrap();
+        assert_eq!(Rcn::try_unwrap(last), Ok(String::from("3")));
+        drop(batch);
+        assert_eq!(*shared, "1");
+        assert!(weak.upgrade().is_none());
+        drop(shared);
+    }
+
+    #[test]
+    fn empty_batch() {
+        let batch: Vec<Rcn<u8>> = Rcn::new_batch(Vec::new());
+        assert!(batch.is_empty());
+    }
+}
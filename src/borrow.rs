@@ -0,0 +1,209 @@
+//! `RcnBorrow<'a, T>`: a borrowed `Rcn` that is passed around without touching the counts.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use crate::alias;
+use crate::count;
+use crate::{Rcn, RcnBox};
+
+/// A borrowed handle to the value of an `Rcn<T>`, or none.
+///
+/// An `RcnBorrow` is a copy of the pointer of the `Rcn` it borrows, so creating, copying and dropping it
+/// never touches the counts, and it derefs without going through the `Rcn`. When an owned handle is needed,
+/// [`share`](#method.share) makes one. Functions that only sometimes keep their argument can take an
+/// `RcnBorrow` instead of an `Rcn` or `&Rcn`, and a none `RcnBorrow` stands for an absent value.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, RcnBorrow};
+///
+/// fn remember(cache: &mut Vec<Rcn<String>>, name: RcnBorrow<String>) {
+///     if name.is_some() && name.len() > 3 {
+///         cache.push(name.share());
+///     }
+/// }
+///
+/// let mut cache = Vec::new();
+/// let name = Rcn::new(String::from("ferris"));
+/// remember(&mut cache, name.as_borrow());
+/// remember(&mut cache, RcnBorrow::none());
+/// assert_eq!(name.strong_count(), 2);
+/// ```
+pub struct RcnBorrow<'a, T: ?Sized> {
+    ptr: NonNull<RcnBox<T>>,
+    phantom: PhantomData<&'a Rcn<T>>,
+}
+
+impl<'a, T> RcnBorrow<'a, T> {
+    /// Constructs an `RcnBorrow<T>` with none value.
+    pub fn none() -> RcnBorrow<'a, T> {
+        RcnBorrow { ptr: RcnBox::dangling(), phantom: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> RcnBorrow<'a, T> {
+    /// Borrows `rcn`.
+    #[inline]
+    pub fn new(rcn: &'a Rcn<T>) -> RcnBorrow<'a, T> {
+        RcnBorrow { ptr: rcn.ptr, phantom: PhantomData }
+    }
+
+    /// Returns `true` if the borrowed `Rcn` has none value.
+    #[inline]
+    pub fn is_none(self) -> bool {
+        self.strong_count() == 0
+    }
+
+    /// Returns `true` if the borrowed `Rcn` has some value.
+    #[inline]
+    pub fn is_some(self) -> bool {
+        self.strong_count() > 0
+    }
+
+    /// Gets the number of strong pointers to the value.
+    #[inline]
+    pub fn strong_count(self) -> usize {
+        if RcnBox::is_dangling(self.ptr) {
+            0
+        } else {
+            unsafe { (*alias::header(self.ptr)).strong.get() }
+        }
+    }
+
+    /// Returns the value for the whole borrow, or `None` if the borrowed `Rcn` has none value.
+    #[inline]
+    pub fn get(self) -> Option<&'a T> {
+        if self.is_some() {
+            Some(unsafe { &*alias::value(self.ptr) })
+        } else {
+            None
+        }
+    }
+
+    /// Makes an owned `Rcn<T>` to the value, increasing the strong count.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the borrowed `Rcn` has none value, like [`Rcn::share`](struct.Rcn.html#method.share).
+    #[track_caller]
+    pub fn share(self) -> Rcn<T> {
+        if self.is_none() {
+            panic!("share of Rcn with none value");
+        }
+        unsafe {
+            let header = alias::header(self.ptr);
+            (*header).strong.set(count::increment((*header).strong.get(), usize::MAX));
+        }
+        Rcn::from_inner(self.ptr)
+    }
+
+    /// Returns `true` if both borrows point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: Self, other: Self) -> bool {
+        match (this.get(), other.get()) {
+            (Some(a), Some(b)) => ptr::addr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for RcnBorrow<'a, T> {
+    #[inline]
+    fn clone(&self) -> RcnBorrow<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T: ?Sized> Copy for RcnBorrow<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for RcnBorrow<'a, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        match self.get() {
+            Some(value) => value,
+            None => panic!("deref of none rcn!"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> From<&'a Rcn<T>> for RcnBorrow<'a, T> {
+    #[inline]
+    fn from(rcn: &'a Rcn<T>) -> RcnBorrow<'a, T> {
+        RcnBorrow::new(rcn)
+    }
+}
+
+impl<'a, T> From<Option<&'a Rcn<T>>> for RcnBorrow<'a, T> {
+    #[inline]
+    fn from(rcn: Option<&'a Rcn<T>>) -> RcnBorrow<'a, T> {
+        rcn.map_or_else(RcnBorrow::none, RcnBorrow::new)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for RcnBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RcnBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => fmt::Debug::fmt(value, f),
+            None => f.write_str("(none)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn total(values: RcnBorrow<[u32]>) -> u32 {
+        values.get().map_or(0, |values| values.iter().sum())
+    }
+
+    #[test]
+    fn borrows_leave_counts_alone() {
+        let values: Rcn<[u32]> = Rcn::from(vec![1, 2, 3]);
+        let borrow = values.as_borrow();
+        let copy = borrow;
+        assert_eq!(total(borrow), 6);
+        assert_eq!(copy.len(), 3);
+        assert_eq!(values.strong_count(), 1);
+
+        let owned = copy.share();
+        assert_eq!(values.strong_count(), 2);
+        assert!(Rcn::ptr_eq(&owned, &values));
+        assert!(RcnBorrow::ptr_eq(borrow, owned.as_borrow()));
+    }
+
+    #[test]
+    fn none_borrows() {
+        let none: Rcn<u8> = Rcn::none();
+        let borrow = RcnBorrow::from(&none);
+        assert!(borrow.is_none());
+        assert_eq!(borrow.get(), None);
+        assert!(RcnBorrow::ptr_eq(borrow, RcnBorrow::from(None)));
+        assert_eq!(format!("{:?}", borrow), "(none)");
+    }
+
+    #[test]
+    fn aliases_can_be_borrowed() {
+        let mut pair = Rcn::new((String::from("left"), 2));
+        let left = Rcn::alias(&mut pair, |p| &mut p.0);
+        let borrow = left.as_borrow();
+        assert_eq!(&*borrow, "left");
+        drop(pair);
+        let owned = borrow.share();
+        assert_eq!(owned.strong_count(), 2);
+    }
+}
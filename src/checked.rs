@@ -0,0 +1,139 @@
+//! Header canaries for use-after-free and double-release detection (requires the `checked` feature).
+//!
+//! Every `RcnBox` carries a canary and a generation tag, and every handle remembers the generation of the box
+//! it points to. When a box is released its canary is poisoned and the memory is kept in a per-thread
+//! quarantine for a while instead of being returned to the allocator, so a stale handle that touches it
+//! finds the poisoned canary (or, once the memory was reused, a different generation) and panics with a
+//! clear message instead of running into undefined behavior.
+
+use std::alloc::{dealloc, Layout};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::panic::Location;
+
+/// Canary of a live box.
+pub(crate) const LIVE: usize = 0x600d_b0c5;
+/// Canary of a released box.
+pub(crate) const FREED: usize = 0xdead_b0c5;
+
+/// Released boxes kept in quarantine before their memory is returned to the allocator.
+const QUARANTINE_LEN: usize = 1024;
+
+struct Quarantine {
+    boxes: VecDeque<(*mut u8, Layout)>,
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        for (ptr, layout) in self.boxes.drain(..) {
+            unsafe { dealloc(ptr, layout) }
+        }
+    }
+}
+
+thread_local! {
+    static GENERATION: Cell<u64> = const { Cell::new(1) };
+    static QUARANTINE: RefCell<Quarantine> = const { RefCell::new(Quarantine { boxes: VecDeque::new() }) };
+}
+
+/// Returns the generation tag for a new box.
+pub(crate) fn next_generation() -> u64 {
+    GENERATION.try_with(|generation| {
+        let next = generation.get();
+        generation.set(next + 1);
+        next
+    }).unwrap_or(0)
+}
+
+/// Panics if a handle of kind `what` with generation `expected` points to a box that is not live.
+#[track_caller]
+pub(crate) fn verify(what: &str, canary: usize, generation: u64, expected: u64) {
+    if canary == FREED {
+        panic!("{} used after box freed at {}", what, Location::caller());
+    }
+    if canary != LIVE {
+        panic!("{} points to a corrupted box at {} (canary {:#x})", what, Location::caller(), canary);
+    }
+    if generation != expected {
+        panic!("{} used after box freed at {} (memory reused by generation {}, handle expects {})",
+            what, Location::caller(), generation, expected);
+    }
+}
+
+/// Panics if `ptr` points into a released box that is still in quarantine.
+#[track_caller]
+pub(crate) fn verify_raw(what: &str, ptr: *const u8) {
+    let address = ptr as usize;
+    let freed = QUARANTINE.try_with(|quarantine| {
+        quarantine.borrow().boxes.iter()
+            .any(|&(start, layout)| address >= start as usize && address < start as usize + layout.size())
+    }).unwrap_or(false);
+    if freed {
+        panic!("{} used after box freed at {}", what, Location::caller());
+    }
+}
+
+/// Moves the memory of a poisoned box into quarantine, releasing the oldest quarantined box if full.
+pub(crate) unsafe fn quarantine(ptr: *mut u8, layout: Layout) {
+    let evicted = QUARANTINE.try_with(|quarantine| {
+        let mut quarantine = quarantine.borrow_mut();
+        quarantine.boxes.push_back((ptr, layout));
+        if quarantine.boxes.len() > QUARANTINE_LEN {
+            quarantine.boxes.pop_front()
+        } else {
+            None
+        }
+    });
+    match evicted {
+        Ok(Some((ptr, layout))) => dealloc(ptr, layout),
+        Ok(None) => {}
+        Err(_) => dealloc(ptr, layout),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rcn, Weakn};
+    use std::mem::ManuallyDrop;
+    use std::ptr;
+
+    #[test]
+    #[should_panic(expected = "Weakn used after box freed at src/checked.rs")]
+    fn upgrade_after_free() {
+        let a = Rcn::new(5);
+        let w = a.downgrade();
+        let stale: ManuallyDrop<Weakn<i32>> = ManuallyDrop::new(unsafe { ptr::read(&w) });
+        drop(w);
+        drop(a);
+        stale.upgrade();
+    }
+
+    #[test]
+    #[should_panic(expected = "Rcn used after box freed")]
+    fn double_release() {
+        let a = Rcn::new(String::from("twice"));
+        let copy: Rcn<String> = unsafe { ptr::read(&a) };
+        drop(a);
+        drop(copy);
+    }
+
+    #[test]
+    #[should_panic(expected = "Rcn::from_raw used after box freed")]
+    fn from_raw_after_free() {
+        let a = Rcn::new(7);
+        let raw: *const i32 = &*a;
+        drop(a);
+        unsafe { Rcn::from_raw(raw) };
+    }
+
+    #[test]
+    fn live_boxes_pass() {
+        let a = Rcn::new(vec![1, 2, 3]);
+        let w = a.downgrade();
+        let b = w.upgrade().unwrap();
+        assert_eq!(b.len(), 3);
+        drop(a);
+        drop(b);
+        assert!(w.upgrade().is_none());
+    }
+}
@@ -0,0 +1,67 @@
+//! Collections of weak handles that drop their expired entries on their own.
+//!
+//! * [`WeaknVec`]: a list of `Weakn`s, compacted while it is iterated.
+//! * [`WeaknSet`]: a set of `Weakn`s keyed by the identity of the box they point to.
+//! * [`WeakKeyMap`]: a map keyed by the identity of `Rcn` boxes, holding its keys weakly.
+//! * [`WeakValueMap`]: a map holding its `Rcn` values weakly.
+//!
+//! All of them purge expired entries automatically once they have grown to twice their size after the last purge,
+//! so cleanup costs amortized constant time per insertion.
+//!
+//! [`WeaknVec`]: struct.WeaknVec.html
+//! [`WeaknSet`]: struct.WeaknSet.html
+//! [`WeakKeyMap`]: struct.WeakKeyMap.html
+//! [`WeakValueMap`]: struct.WeakValueMap.html
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate rcn;
+//! use rcn::Rcn;
+//! use rcn::collections::WeaknVec;
+//!
+//! let first = Rcn::new("first");
+//! let second = Rcn::new("second");
+//! let mut listeners = WeaknVec::new();
+//! listeners.push(first.downgrade());
+//! listeners.push(second.downgrade());
+//!
+//! drop(first);
+//! let live: Vec<&str> = listeners.iter_upgraded().map(|l| *l).collect();
+//! assert_eq!(live, ["second"]);
+//! assert_eq!(listeners.len(), 1);
+//! ```
+
+mod set;
+mod vec;
+pub mod weak_key_map;
+pub mod weak_value_map;
+
+pub use self::set::WeaknSet;
+pub use self::vec::{WeaknVec, Upgraded};
+pub use self::weak_key_map::WeakKeyMap;
+pub use self::weak_value_map::WeakValueMap;
+
+use crate::{Rcn, RcnBox, Weakn};
+
+/// Smallest number of entries that triggers an automatic purge.
+const MIN_PURGE: usize = 8;
+
+/// Returns the length after which a collection of `len` live entries purges again.
+fn next_purge(len: usize) -> usize {
+    (len * 2).max(MIN_PURGE)
+}
+
+fn address<T: ?Sized>(ptr: *mut RcnBox<T>) -> usize {
+    ptr as *const () as usize
+}
+
+/// Returns the identity of the box behind `weak`.
+fn weak_key<T: ?Sized>(weak: &Weakn<T>) -> usize {
+    address(weak.inner().as_ptr())
+}
+
+/// Returns the identity of the box behind `rcn`.
+fn rcn_key<T: ?Sized>(rcn: &Rcn<T>) -> usize {
+    address(rcn.ptr.as_ptr())
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{next_purge, rcn_key, weak_key};
+use crate::{Rcn, Weakn};
+
+/// A set of `Weakn<T>` keyed by the identity of the box they point to, which purges expired entries
+/// automatically as it grows.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::Rcn;
+/// use rcn::collections::WeaknSet;
+///
+/// let a = Rcn::new(1);
+/// let b = Rcn::new(1);
+/// let mut observers = WeaknSet::new();
+/// assert!(observers.insert(a.downgrade()));
+/// assert!(!observers.insert(a.downgrade()));
+/// assert!(observers.insert(b.downgrade()));
+///
+/// drop(b);
+/// assert_eq!(observers.len_live(), 1);
+/// assert!(observers.contains(&a));
+/// ```
+pub struct WeaknSet<T: ?Sized> {
+    items: HashMap<usize, Weakn<T>>,
+    purge_at: usize,
+}
+
+impl<T: ?Sized> WeaknSet<T> {
+    /// Constructs an empty set.
+    pub fn new() -> WeaknSet<T> {
+        WeaknSet { items: HashMap::new(), purge_at: next_purge(0) }
+    }
+
+    /// Adds `weak` unless its box is already in the set or its value is gone. Returns `true` if it was
+    /// added.
+    pub fn insert(&mut self, weak: Weakn<T>) -> bool {
+        if weak.is_none() {
+            return false;
+        }
+        if self.items.len() >= self.purge_at {
+            self.purge();
+        }
+        let key = weak_key(&weak);
+        if self.items.contains_key(&key) {
+            return false;
+        }
+        self.items.insert(key, weak);
+        true
+    }
+
+    /// Returns `true` if the box of `rcn` is in the set.
+    pub fn contains(&self, rcn: &Rcn<T>) -> bool {
+        self.items.contains_key(&rcn_key(rcn))
+    }
+
+    /// Removes the box of `rcn` from the set. Returns `true` if it was there.
+    pub fn remove(&mut self, rcn: &Rcn<T>) -> bool {
+        self.items.remove(&rcn_key(rcn)).is_some()
+    }
+
+    /// Returns the number of entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the set has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the number of entries whose value is still alive.
+    pub fn len_live(&self) -> usize {
+        self.items.values().filter(|weak| weak.is_some()).count()
+    }
+
+    /// Removes the expired entries and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let before = self.items.len();
+        self.items.retain(|_, weak| weak.is_some());
+        self.purge_at = next_purge(self.items.len());
+        before - self.items.len()
+    }
+
+    /// Keeps the live entries for which `f` returns `true`, and removes the others and the expired ones.
+    pub fn retain_live<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.items.retain(|_, weak| match weak.upgrade() {
+            Some(rcn) => f(&rcn),
+            None => false,
+        });
+        self.purge_at = next_purge(self.items.len());
+    }
+
+    /// Returns an iterator over the live values, in no particular order.
+    pub fn iter_upgraded(&self) -> impl Iterator<Item = Rcn<T>> + '_ {
+        self.items.values().filter_map(Weakn::upgrade)
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.purge_at = next_purge(0);
+    }
+}
+
+impl<T: ?Sized> Default for WeaknSet<T> {
+    fn default() -> WeaknSet<T> {
+        WeaknSet::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for WeaknSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WeaknSet").field("len", &self.len()).field("live", &self.len_live()).finish()
+    }
+}
+
+impl<T: ?Sized> Extend<Weakn<T>> for WeaknSet<T> {
+    fn extend<I: IntoIterator<Item = Weakn<T>>>(&mut self, iter: I) {
+        for weak in iter {
+            self.insert(weak);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identity_and_cleanup() {
+        let names: Rcn<str> = Rcn::from("names");
+        let mut set = WeaknSet::new();
+        assert!(set.insert(names.downgrade()));
+        assert!(!set.insert(names.downgrade()));
+        for _ in 0..100 {
+            let temp: Rcn<str> = Rcn::from("temp");
+            set.insert(temp.downgrade());
+        }
+        assert!(set.len() <= 16);
+        assert_eq!(set.iter_upgraded().map(|s| s.len()).sum::<usize>(), 5);
+
+        set.retain_live(|s| s.starts_with('x'));
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use super::next_purge;
+use crate::{Rcn, Weakn};
+
+/// A list of `Weakn<T>` that drops expired entries while it is iterated, and purges them automatically as it
+/// grows.
+pub struct WeaknVec<T: ?Sized> {
+    items: Vec<Weakn<T>>,
+    purge_at: usize,
+}
+
+impl<T: ?Sized> WeaknVec<T> {
+    /// Constructs an empty list.
+    pub fn new() -> WeaknVec<T> {
+        WeaknVec { items: Vec::new(), purge_at: next_purge(0) }
+    }
+
+    /// Appends `weak`, purging expired entries first if the list doubled since the last purge.
+    pub fn push(&mut self, weak: Weakn<T>) {
+        if self.items.len() >= self.purge_at {
+            self.purge();
+        }
+        self.items.push(weak);
+    }
+
+    /// Returns the number of entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the list has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the number of entries whose value is still alive.
+    pub fn len_live(&self) -> usize {
+        self.items.iter().filter(|weak| weak.is_some()).count()
+    }
+
+    /// Removes the expired entries and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let before = self.items.len();
+        self.items.retain(|weak| weak.is_some());
+        self.purge_at = next_purge(self.items.len());
+        before - self.items.len()
+    }
+
+    /// Keeps the live entries for which `f` returns `true`, and removes the others and the expired ones.
+    pub fn retain_live<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.items.retain(|weak| match weak.upgrade() {
+            Some(rcn) => f(&rcn),
+            None => false,
+        });
+        self.purge_at = next_purge(self.items.len());
+    }
+
+    /// Returns an iterator over the live values, in insertion order. Expired entries met on the way are
+    /// removed from the list.
+    pub fn iter_upgraded(&mut self) -> Upgraded<'_, T> {
+        Upgraded { items: &mut self.items, read: 0, write: 0 }
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.purge_at = next_purge(0);
+    }
+}
+
+/// Iterator returned by [`WeaknVec::iter_upgraded`](struct.WeaknVec.html#method.iter_upgraded).
+///
+/// Live entries are moved down over the expired ones as they are visited; the expired ones are dropped
+/// when the iterator is.
+pub struct Upgraded<'a, T: ?Sized> {
+    items: &'a mut Vec<Weakn<T>>,
+    read: usize,
+    write: usize,
+}
+
+impl<'a, T: ?Sized> Iterator for Upgraded<'a, T> {
+    type Item = Rcn<T>;
+
+    fn next(&mut self) -> Option<Rcn<T>> {
+        while self.read < self.items.len() {
+            let upgraded = self.items[self.read].upgrade();
+            self.read += 1;
+            if let Some(rcn) = upgraded {
+                self.items.swap(self.write, self.read - 1);
+                self.write += 1;
+                return Some(rcn);
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T: ?Sized> Drop for Upgraded<'a, T> {
+    fn drop(&mut self) {
+        // Entries not visited are kept, whether expired or not.
+        for i in self.read..self.items.len() {
+            self.items.swap(self.write, i);
+            self.write += 1;
+        }
+        self.items.truncate(self.write);
+    }
+}
+
+impl<T: ?Sized> Default for WeaknVec<T> {
+    fn default() -> WeaknVec<T> {
+        WeaknVec::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for WeaknVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WeaknVec").field("len", &self.len()).field("live", &self.len_live()).finish()
+    }
+}
+
+impl<T: ?Sized> Extend<Weakn<T>> for WeaknVec<T> {
+    fn extend<I: IntoIterator<Item = Weakn<T>>>(&mut self, iter: I) {
+        for weak in iter {
+            self.push(weak);
+        }
+    }
+}
+
+impl<T: ?Sized> FromIterator<Weakn<T>> for WeaknVec<T> {
+    fn from_iter<I: IntoIterator<Item = Weakn<T>>>(iter: I) -> WeaknVec<T> {
+        let mut list = WeaknVec::new();
+        list.extend(iter);
+        list
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn iteration_compacts() {
+        let values: Vec<Rcn<i32>> = (0..6).map(Rcn::new).collect();
+        let mut list: WeaknVec<i32> = values.iter().map(Rcn::downgrade).collect();
+        let mut values: Vec<Option<Rcn<i32>>> = values.into_iter().map(Some).collect();
+        values[1] = None;
+        values[4] = None;
+        assert_eq!(list.len_live(), 4);
+
+        let mut iter = list.iter_upgraded();
+        assert_eq!(*iter.next().unwrap(), 0);
+        assert_eq!(*iter.next().unwrap(), 2);
+        drop(iter);
+        assert_eq!(list.len(), 5);
+
+        let live: Vec<i32> = list.iter_upgraded().map(|rcn| *rcn).collect();
+        assert_eq!(live, [0, 2, 3, 5]);
+        assert_eq!(list.len(), 4);
+
+        list.retain_live(|&value| value > 2);
+        assert_eq!(list.iter_upgraded().map(|rcn| *rcn).collect::<Vec<_>>(), [3, 5]);
+    }
+
+    #[test]
+    fn push_purges_automatically() {
+        let mut list = WeaknVec::new();
+        for i in 0..1000 {
+            list.push(Rcn::new(i).downgrade());
+        }
+        assert!(list.len() <= 8);
+        assert_eq!(list.len_live(), 0);
+    }
+}
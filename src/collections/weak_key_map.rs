@@ -0,0 +1,256 @@
+//! A hash map keyed by the identity of `Rcn` boxes, holding its keys weakly.
+
+use std::collections::hash_map::{self, HashMap};
+use std::fmt;
+
+use super::{next_purge, rcn_key};
+use crate::{Rcn, Weakn};
+
+/// A map from `Rcn<K>` boxes to values of type `V` that does not keep its keys alive. An entry expires
+/// when the last `Rcn` of its key drops; expired entries are skipped by lookups and iteration, and purged
+/// automatically once the map has doubled since the last purge.
+///
+/// Keys are compared by box identity, as [`Rcn::ptr_eq`](../../struct.Rcn.html#method.ptr_eq) does.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::Rcn;
+/// use rcn::collections::WeakKeyMap;
+///
+/// let mesh = Rcn::new(vec![1.0, 2.0, 3.0]);
+/// let mut areas = WeakKeyMap::new();
+/// let area = *areas.get_or_insert_with(&mesh, || mesh.iter().sum::<f64>());
+/// assert_eq!(area, 6.0);
+///
+/// drop(mesh);
+/// assert_eq!(areas.len_live(), 0);
+/// assert_eq!(areas.purge(), 1);
+/// ```
+pub struct WeakKeyMap<K: ?Sized, V> {
+    entries: HashMap<usize, (Weakn<K>, V)>,
+    purge_at: usize,
+}
+
+impl<K: ?Sized, V> WeakKeyMap<K, V> {
+    /// Constructs an empty map.
+    pub fn new() -> WeakKeyMap<K, V> {
+        WeakKeyMap { entries: HashMap::new(), purge_at: next_purge(0) }
+    }
+
+    /// Purges expired entries if the map doubled since the last purge.
+    fn maybe_purge(&mut self) {
+        if self.entries.len() >= self.purge_at {
+            self.purge();
+        }
+    }
+
+    /// Inserts `value` for `key`, returning the previous value of `key`, if any.
+    pub fn insert(&mut self, key: &Rcn<K>, value: V) -> Option<V> {
+        self.maybe_purge();
+        self.entries.insert(rcn_key(key), (key.downgrade(), value)).map(|(_, old)| old)
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &Rcn<K>) -> Option<&V> {
+        self.entries.get(&rcn_key(key)).map(|(_, value)| value)
+    }
+
+    /// Returns the value of `key` mutably.
+    pub fn get_mut(&mut self, key: &Rcn<K>) -> Option<&mut V> {
+        self.entries.get_mut(&rcn_key(key)).map(|(_, value)| value)
+    }
+
+    /// Returns `true` if the map has a value for `key`.
+    pub fn contains_key(&self, key: &Rcn<K>) -> bool {
+        self.entries.contains_key(&rcn_key(key))
+    }
+
+    /// Removes and returns the value of `key`.
+    pub fn remove(&mut self, key: &Rcn<K>) -> Option<V> {
+        self.entries.remove(&rcn_key(key)).map(|(_, value)| value)
+    }
+
+    /// Returns the entry of `key` for in-place manipulation.
+    pub fn entry(&mut self, key: &Rcn<K>) -> Entry<'_, K, V> {
+        self.maybe_purge();
+        match self.entries.entry(rcn_key(key)) {
+            hash_map::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry { inner }),
+            hash_map::Entry::Vacant(inner) => Entry::Vacant(VacantEntry { inner, key: key.downgrade() }),
+        }
+    }
+
+    /// Returns the value of `key`, inserting the result of `f` first if there is none.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: &Rcn<K>, f: F) -> &mut V {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Removes the expired entries and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, (key, _)| key.is_some());
+        self.purge_at = next_purge(self.entries.len());
+        before - self.entries.len()
+    }
+
+    /// Returns the number of entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of entries whose key is still alive.
+    pub fn len_live(&self) -> usize {
+        self.entries.values().filter(|(key, _)| key.is_some()).count()
+    }
+
+    /// Returns an iterator over the live entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Rcn<K>, &V)> + '_ {
+        self.entries.values().filter_map(|(key, value)| key.upgrade().map(|key| (key, value)))
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.purge_at = next_purge(0);
+    }
+}
+
+/// An entry of a [`WeakKeyMap`](struct.WeakKeyMap.html), returned by
+/// [`WeakKeyMap::entry`](struct.WeakKeyMap.html#method.entry).
+pub enum Entry<'a, K: ?Sized, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry of a key with a value.
+pub struct OccupiedEntry<'a, K: ?Sized, V> {
+    inner: hash_map::OccupiedEntry<'a, usize, (Weakn<K>, V)>,
+}
+
+/// An entry of a key without a value.
+pub struct VacantEntry<'a, K: ?Sized, V> {
+    inner: hash_map::VacantEntry<'a, usize, (Weakn<K>, V)>,
+    key: Weakn<K>,
+}
+
+impl<'a, K: ?Sized, V> Entry<'a, K, V> {
+    /// Returns the value, inserting `default` first if there is none.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value, inserting the result of `f` first if there is none.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Returns the value, inserting `V::default()` first if there is none.
+    pub fn or_default(self) -> &'a mut V where V: Default {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value if there is one.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Entry<'a, K, V> {
+        if let Entry::Occupied(ref mut entry) = self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: ?Sized, V> OccupiedEntry<'a, K, V> {
+    /// Returns the value.
+    pub fn get(&self) -> &V {
+        &self.inner.get().1
+    }
+
+    /// Returns the value mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.inner.get_mut().1
+    }
+
+    /// Returns the value mutably, for the lifetime of the map borrow.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.inner.into_mut().1
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry and returns its value.
+    pub fn remove(self) -> V {
+        self.inner.remove().1
+    }
+}
+
+impl<'a, K: ?Sized, V> VacantEntry<'a, K, V> {
+    /// Inserts `value` and returns it mutably.
+    pub fn insert(self, value: V) -> &'a mut V {
+        &mut self.inner.insert((self.key, value)).1
+    }
+}
+
+impl<K: ?Sized, V> Default for WeakKeyMap<K, V> {
+    fn default() -> WeakKeyMap<K, V> {
+        WeakKeyMap::new()
+    }
+}
+
+impl<K: ?Sized, V: fmt::Debug> fmt::Debug for WeakKeyMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|(_, value)| value)).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entries_expire_with_their_keys() {
+        let a: Rcn<str> = Rcn::from("a");
+        let b: Rcn<str> = Rcn::from("a");
+        let mut map = WeakKeyMap::new();
+        *map.entry(&a).or_insert(0) += 1;
+        *map.entry(&a).or_insert(0) += 1;
+        map.entry(&b).and_modify(|count| *count += 10).or_insert(5);
+        assert_eq!(map.get(&a), Some(&2));
+        assert_eq!(map.get(&b), Some(&5));
+
+        let weak = b.downgrade();
+        drop(b);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.len_live(), 1);
+        assert_eq!(map.iter().count(), 1);
+        assert_eq!(map.purge(), 1);
+        assert!(weak.upgrade().is_none());
+        if let Entry::Occupied(entry) = map.entry(&a) {
+            assert_eq!(entry.remove(), 2);
+        }
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn inserts_purge_amortized() {
+        let mut map = WeakKeyMap::new();
+        let kept = Rcn::new(0);
+        map.insert(&kept, 0);
+        for i in 1..1000 {
+            let key = Rcn::new(i);
+            map.insert(&key, i);
+        }
+        assert!(map.len() <= 16);
+        assert_eq!(map.get(&kept), Some(&0));
+    }
+}
@@ -0,0 +1,261 @@
+//! A hash map holding its values weakly.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::{self, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+use super::next_purge;
+use crate::{Rcn, Weakn};
+
+/// A map from keys of type `K` to `Rcn<V>` values that does not keep its values alive. An entry expires
+/// when the last `Rcn` of its value drops; expired entries are skipped by lookups and iteration, and
+/// purged automatically once the map has doubled since the last purge.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::Rcn;
+/// use rcn::collections::WeakValueMap;
+///
+/// let mut textures = WeakValueMap::new();
+/// let grass = textures.get_or_insert_with("grass.png", || Rcn::new(vec![0u8; 16]));
+/// assert!(Rcn::ptr_eq(&grass, &textures.get("grass.png").unwrap()));
+///
+/// drop(grass);
+/// assert!(textures.get("grass.png").is_none());
+/// ```
+pub struct WeakValueMap<K, V: ?Sized> {
+    entries: HashMap<K, Weakn<V>>,
+    purge_at: usize,
+}
+
+impl<K: Hash + Eq, V: ?Sized> WeakValueMap<K, V> {
+    /// Constructs an empty map.
+    pub fn new() -> WeakValueMap<K, V> {
+        WeakValueMap { entries: HashMap::new(), purge_at: next_purge(0) }
+    }
+
+    /// Purges expired entries if the map doubled since the last purge.
+    fn maybe_purge(&mut self) {
+        if self.entries.len() >= self.purge_at {
+            self.purge();
+        }
+    }
+
+    /// Inserts `value` for `key`, returning the previous value of `key` if it is still alive.
+    pub fn insert(&mut self, key: K, value: &Rcn<V>) -> Option<Rcn<V>> {
+        self.maybe_purge();
+        self.entries.insert(key, value.downgrade()).and_then(|old| old.upgrade())
+    }
+
+    /// Returns the value of `key`, if it is still alive.
+    pub fn get<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<Rcn<V>> where K: Borrow<Q> {
+        self.entries.get(key).and_then(Weakn::upgrade)
+    }
+
+    /// Returns `true` if the map has a live value for `key`.
+    pub fn contains_key<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.entries.get(key).is_some_and(Weakn::is_some)
+    }
+
+    /// Removes the entry of `key`, returning its value if it is still alive.
+    pub fn remove<Q: ?Sized + Hash + Eq>(&mut self, key: &Q) -> Option<Rcn<V>> where K: Borrow<Q> {
+        self.entries.remove(key).and_then(|old| old.upgrade())
+    }
+
+    /// Returns the entry of `key` for in-place manipulation. An expired entry is vacant.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        self.maybe_purge();
+        match self.entries.entry(key) {
+            hash_map::Entry::Occupied(inner) => match inner.get().upgrade() {
+                Some(value) => Entry::Occupied(OccupiedEntry { inner, value }),
+                None => Entry::Vacant(VacantEntry { inner: Vacancy::Expired(inner) }),
+            },
+            hash_map::Entry::Vacant(inner) => Entry::Vacant(VacantEntry { inner: Vacancy::Missing(inner) }),
+        }
+    }
+
+    /// Returns the value of `key`, inserting the result of `f` first if there is no live one.
+    pub fn get_or_insert_with<F: FnOnce() -> Rcn<V>>(&mut self, key: K, f: F) -> Rcn<V> {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Removes the expired entries and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, value| value.is_some());
+        self.purge_at = next_purge(self.entries.len());
+        before - self.entries.len()
+    }
+
+    /// Returns the number of entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of entries whose value is still alive.
+    pub fn len_live(&self) -> usize {
+        self.entries.values().filter(|value| value.is_some()).count()
+    }
+
+    /// Returns an iterator over the live entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, Rcn<V>)> + '_ {
+        self.entries.iter().filter_map(|(key, value)| value.upgrade().map(|value| (key, value)))
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.purge_at = next_purge(0);
+    }
+}
+
+/// An entry of a [`WeakValueMap`](struct.WeakValueMap.html), returned by
+/// [`WeakValueMap::entry`](struct.WeakValueMap.html#method.entry).
+pub enum Entry<'a, K, V: ?Sized> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry of a key with a live value.
+pub struct OccupiedEntry<'a, K, V: ?Sized> {
+    inner: hash_map::OccupiedEntry<'a, K, Weakn<V>>,
+    value: Rcn<V>,
+}
+
+/// An entry of a key without a value, or whose value expired.
+pub struct VacantEntry<'a, K, V: ?Sized> {
+    inner: Vacancy<'a, K, V>,
+}
+
+enum Vacancy<'a, K, V: ?Sized> {
+    Missing(hash_map::VacantEntry<'a, K, Weakn<V>>),
+    Expired(hash_map::OccupiedEntry<'a, K, Weakn<V>>),
+}
+
+impl<'a, K, V: ?Sized> Entry<'a, K, V> {
+    /// Returns the value, inserting `default` first if there is no live one.
+    pub fn or_insert(self, default: Rcn<V>) -> Rcn<V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value, inserting the result of `f` first if there is no live one.
+    pub fn or_insert_with<F: FnOnce() -> Rcn<V>>(self, f: F) -> Rcn<V> {
+        match self {
+            Entry::Occupied(entry) => entry.value,
+            Entry::Vacant(entry) => {
+                let value = f();
+                entry.insert(&value);
+                value
+            }
+        }
+    }
+
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.inner.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<'a, K, V: ?Sized> OccupiedEntry<'a, K, V> {
+    /// Returns the value.
+    pub fn get(&self) -> &Rcn<V> {
+        &self.value
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: &Rcn<V>) -> Rcn<V> {
+        self.inner.insert(value.downgrade());
+        std::mem::replace(&mut self.value, value.share())
+    }
+
+    /// Removes the entry and returns its value.
+    pub fn remove(self) -> Rcn<V> {
+        self.inner.remove();
+        self.value
+    }
+}
+
+impl<'a, K, V: ?Sized> VacantEntry<'a, K, V> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        match self.inner {
+            Vacancy::Missing(ref inner) => inner.key(),
+            Vacancy::Expired(ref inner) => inner.key(),
+        }
+    }
+
+    /// Stores a weak handle to `value` in this entry.
+    pub fn insert(self, value: &Rcn<V>) {
+        match self.inner {
+            Vacancy::Missing(inner) => {
+                inner.insert(value.downgrade());
+            }
+            Vacancy::Expired(mut inner) => {
+                inner.insert(value.downgrade());
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: ?Sized> Default for WeakValueMap<K, V> {
+    fn default() -> WeakValueMap<K, V> {
+        WeakValueMap::new()
+    }
+}
+
+impl<K: Hash + Eq + fmt::Debug, V: ?Sized + fmt::Debug> fmt::Debug for WeakValueMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expired_entries_are_vacant() {
+        let mut map: WeakValueMap<String, str> = WeakValueMap::new();
+        let first: Rcn<str> = Rcn::from("first");
+        map.insert(String::from("k"), &first);
+        assert!(matches!(map.entry(String::from("k")), Entry::Occupied(_)));
+        drop(first);
+        assert!(!map.contains_key("k"));
+        assert_eq!(map.len(), 1);
+
+        let second = match map.entry(String::from("k")) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), "k");
+                let value: Rcn<str> = Rcn::from("second");
+                entry.insert(&value);
+                value
+            }
+            Entry::Occupied(_) => unreachable!(),
+        };
+        assert_eq!(&*map.get("k").unwrap(), "second");
+        assert_eq!(map.len(), 1);
+        assert!(Rcn::ptr_eq(&map.remove("k").unwrap(), &second));
+    }
+
+    #[test]
+    fn inserts_purge_amortized() {
+        let mut map = WeakValueMap::new();
+        for i in 0..1000 {
+            let value = Rcn::new(i);
+            map.insert(i, &value);
+        }
+        assert!(map.len() <= 16);
+        assert_eq!(map.len_live(), 0);
+    }
+}
@@ -0,0 +1,710 @@
+//! Reference-counting pointers with compact headers.
+//!
+//! Every `Rcn` box carries two `usize` counts, which is 16 bytes of header on 64-bit targets. For large numbers
+//! of small shared values the header dominates memory, so this module provides pointers whose counts have a
+//! configurable width:
+//!
+//! * [`CompactRcn<T, C>`] and [`CompactWeakn<T, C>`] store both counts as `C` (`u8`, `u16`, `u32`, `u64` or
+//!   `usize`), so a `CompactRcn<u32, u16>` box takes 8 bytes instead of 24.
+//! * [`RcnNoWeak<T, C>`] drops weak support entirely and only stores a strong count.
+//!
+//! Overflow checking respects the chosen width: sharing a `CompactRcn<T, u8>` for the 255th time applies the
+//! thread's [`OverflowPolicy`], and `try_share` reports an [`OverflowError`]. The `leak-check` feature
+//! records compact boxes too; `track-holders` and `checked` only cover `Rcn` and `Weakn`.
+//!
+//! [`CompactRcn<T, C>`]: struct.CompactRcn.html
+//! [`CompactWeakn<T, C>`]: struct.CompactWeakn.html
+//! [`RcnNoWeak<T, C>`]: struct.RcnNoWeak.html
+//! [`OverflowPolicy`]: ../enum.OverflowPolicy.html
+//! [`OverflowError`]: ../struct.OverflowError.html
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate rcn;
+//! use rcn::compact::{CompactRcn, RcnNoWeak};
+//!
+//! let node: CompactRcn<u32, u16> = CompactRcn::new(7);
+//! let shared = node.share();
+//! let weak = node.downgrade();
+//! assert_eq!(*shared, 7);
+//! assert_eq!((node.strong_count(), node.weak_count()), (2, 1));
+//!
+//! let token: RcnNoWeak<u32, u32> = RcnNoWeak::new(1);
+//! assert_eq!(token.share().strong_count(), 2);
+//! # drop(weak);
+//! ```
+
+use std::alloc::{dealloc, Layout};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+#[cfg(feature = "leak-check")]
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+use crate::count;
+use crate::OverflowError;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An unsigned integer type usable as reference count by the compact pointers.
+pub trait Counter: Copy + sealed::Sealed {
+    /// The largest count representable by this type, as `usize`.
+    const MAX: usize;
+
+    #[doc(hidden)]
+    fn to_usize(self) -> usize;
+
+    #[doc(hidden)]
+    fn from_usize(count: usize) -> Self;
+}
+
+macro_rules! counter {
+    ($($t:ty),*) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl Counter for $t {
+            const MAX: usize = if (<$t>::MAX as u128) < (usize::MAX as u128) { <$t>::MAX as usize } else { usize::MAX };
+
+            #[inline(always)]
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+
+            #[inline(always)]
+            fn from_usize(count: usize) -> Self {
+                count as $t
+            }
+        }
+    )*};
+}
+
+counter!(u8, u16, u32, u64, usize);
+
+struct CompactBox<T: ?Sized, C> {
+    strong: Cell<C>,
+    weak: Cell<C>,
+    value: T,
+}
+
+#[inline]
+unsafe fn get<C: Counter>(cell: *const Cell<C>) -> usize {
+    (*cell).get().to_usize()
+}
+
+#[inline]
+unsafe fn set<C: Counter>(cell: *const Cell<C>, count: usize) {
+    (*cell).set(C::from_usize(count))
+}
+
+/// A single-threaded reference-counting pointer with none value and `C`-sized counts.
+///
+/// See the [module documentation](index.html) for details.
+pub struct CompactRcn<T: ?Sized, C: Counter = u32> {
+    ptr: *mut CompactBox<T, C>,
+    phantom: PhantomData<T>,
+}
+
+/// A weak version of [`CompactRcn`](struct.CompactRcn.html).
+pub struct CompactWeakn<T: ?Sized, C: Counter = u32> {
+    ptr: *mut CompactBox<T, C>,
+}
+
+impl<T, C: Counter> CompactRcn<T, C> {
+    /// Constructs a new `CompactRcn<T, C>`.
+    pub fn new(data: T) -> CompactRcn<T, C> {
+        let ptr = Box::into_raw(Box::new(CompactBox {
+            strong: Cell::new(C::from_usize(1)),
+            weak: Cell::new(C::from_usize(0)),
+            value: data,
+        }));
+        #[cfg(feature = "leak-check")]
+        unsafe {
+            crate::debug::register_counts::<T, C>(ptr, mem::size_of_val(&*ptr), &(*ptr).strong, &(*ptr).weak);
+        }
+        CompactRcn { ptr, phantom: PhantomData }
+    }
+
+    /// Constructs a `CompactRcn<T, C>` with none value.
+    pub fn none() -> CompactRcn<T, C> {
+        CompactRcn { ptr: ptr::null_mut(), phantom: PhantomData }
+    }
+
+    /// Takes the value out, leaving a none in its place. Returns `None` if the pointer is not unique.
+    pub fn take(&mut self) -> Option<T> {
+        if self.is_unique() {
+            unsafe {
+                let out_ptr = self.ptr;
+                self.ptr = ptr::null_mut();
+                let value = ptr::read(&(*out_ptr).value);
+                deallocate(out_ptr);
+                Some(value)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained value if the pointer has exactly one strong reference, otherwise returns it back.
+    ///
+    /// This will succeed even if there are outstanding weak references.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this.strong_count() == 1 {
+            unsafe {
+                let val = ptr::read(&(*this.ptr).value);
+                set(&(*this.ptr).strong, 0);
+                if this.weak_count() == 0 {
+                    deallocate(this.ptr);
+                }
+                std::mem::forget(this);
+                Ok(val)
+            }
+        } else {
+            Err(this)
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> CompactRcn<T, C> {
+    /// Gets the number of strong pointers to this value.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        if self.ptr.is_null() { 0 } else { unsafe { get(&(*self.ptr).strong) } }
+    }
+
+    /// Gets the number of weak pointers to this value.
+    #[inline]
+    pub fn weak_count(&self) -> usize {
+        if self.ptr.is_null() { 0 } else { unsafe { get(&(*self.ptr).weak) } }
+    }
+
+    /// Returns `true` if `weak_count == 0` and `strong_count == 1`.
+    #[inline]
+    pub fn is_unique(&self) -> bool {
+        self.weak_count() == 0 && self.strong_count() == 1
+    }
+
+    /// Returns `true` if the current pointer is `None`.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.strong_count() == 0
+    }
+
+    /// Returns `true` if the current pointer is not `None`.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.strong_count() > 0
+    }
+
+    /// Returns true if the two pointers point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr, other.ptr)
+    }
+
+    /// Creates another pointer to the same value, increasing the strong count.
+    pub fn share(&self) -> CompactRcn<T, C> {
+        if self.is_some() {
+            unsafe { set(&(*self.ptr).strong, count::increment(self.strong_count(), C::MAX)) };
+            CompactRcn { ptr: self.ptr, phantom: PhantomData }
+        } else {
+            panic!("share of CompactRcn with none value");
+        }
+    }
+
+    /// Like [`share`](#method.share), but returns an error when the strong count is at the limit of `C`.
+    pub fn try_share(&self) -> Result<CompactRcn<T, C>, OverflowError> {
+        if self.is_some() {
+            let strong = count::try_increment(self.strong_count(), C::MAX)?;
+            unsafe { set(&(*self.ptr).strong, strong) };
+            Ok(CompactRcn { ptr: self.ptr, phantom: PhantomData })
+        } else {
+            panic!("share of CompactRcn with none value");
+        }
+    }
+
+    /// Creates a new [`CompactWeakn`](struct.CompactWeakn.html) pointer to this value. Downgrading a none
+    /// pointer yields a none `CompactWeakn`.
+    pub fn downgrade(&self) -> CompactWeakn<T, C> {
+        if self.is_some() {
+            unsafe { set(&(*self.ptr).weak, count::increment(self.weak_count(), C::MAX)) };
+        }
+        // A none pointer is null, and so is the weak pointer made from it.
+        CompactWeakn { ptr: self.ptr }
+    }
+
+    /// Like [`downgrade`](#method.downgrade), but returns an error when the weak count is at the limit of `C`.
+    pub fn try_downgrade(&self) -> Result<CompactWeakn<T, C>, OverflowError> {
+        if self.is_some() {
+            let weak = count::try_increment(self.weak_count(), C::MAX)?;
+            unsafe { set(&(*self.ptr).weak, weak) };
+        }
+        Ok(CompactWeakn { ptr: self.ptr })
+    }
+}
+
+impl<T: Clone, C: Counter> CompactRcn<T, C> {
+    /// Get a clone of internal data.
+    pub fn get(&self) -> T {
+        (**self).clone()
+    }
+
+    /// Overwrites the shared value with a clone of `data`.
+    pub fn set(&mut self, data: &T) {
+        **self = data.clone();
+    }
+}
+
+unsafe fn deallocate<T: ?Sized, C>(ptr: *mut CompactBox<T, C>) {
+    #[cfg(feature = "leak-check")]
+    crate::debug::unregister(ptr);
+    dealloc(ptr as *mut u8, Layout::for_value(&*ptr));
+}
+
+impl<T: ?Sized, C: Counter> Drop for CompactRcn<T, C> {
+    fn drop(&mut self) {
+        if self.is_some() {
+            unsafe {
+                let strong = count::decrement(self.strong_count(), C::MAX, "strong");
+                set(&(*self.ptr).strong, strong);
+                if strong == 0 {
+                    // Same as `Rcn`: a weak reference keeps the box alive while the value drops.
+                    set(&(*self.ptr).weak, count::increment(self.weak_count(), C::MAX));
+                    ptr::drop_in_place(&mut (*self.ptr).value);
+                    let weak = count::decrement(self.weak_count(), C::MAX, "weak");
+                    set(&(*self.ptr).weak, weak);
+                    if weak == 0 {
+                        deallocate(self.ptr);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> Deref for CompactRcn<T, C> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        if self.is_some() {
+            unsafe { &(*self.ptr).value }
+        } else {
+            panic!("deref of none rcn!");
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> DerefMut for CompactRcn<T, C> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        if self.is_some() {
+            unsafe { &mut (*self.ptr).value }
+        } else {
+            panic!("deref_mut of none rcn!");
+        }
+    }
+}
+
+impl<T: Default, C: Counter> Default for CompactRcn<T, C> {
+    fn default() -> CompactRcn<T, C> {
+        CompactRcn::new(Default::default())
+    }
+}
+
+impl<T, C: Counter> From<T> for CompactRcn<T, C> {
+    fn from(t: T) -> Self {
+        CompactRcn::new(t)
+    }
+}
+
+impl<T: ?Sized + fmt::Display, C: Counter> fmt::Display for CompactRcn<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, C: Counter> fmt::Debug for CompactRcn<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + PartialEq, C: Counter> PartialEq for CompactRcn<T, C> {
+    fn eq(&self, other: &CompactRcn<T, C>) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq, C: Counter> Eq for CompactRcn<T, C> {}
+
+impl<T: ?Sized + PartialOrd, C: Counter> PartialOrd for CompactRcn<T, C> {
+    fn partial_cmp(&self, other: &CompactRcn<T, C>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T, C: Counter> CompactWeakn<T, C> {
+    /// Constructs a `CompactWeakn<T, C>` with none value.
+    pub fn new() -> CompactWeakn<T, C> {
+        CompactWeakn { ptr: ptr::null_mut() }
+    }
+}
+
+impl<T: ?Sized, C: Counter> CompactWeakn<T, C> {
+    /// Returns `true` if the value was dropped or the pointer is none.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_null() || unsafe { get(&(*self.ptr).strong) } == 0
+    }
+
+    /// Returns `true` if the value is still alive.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        !self.is_none()
+    }
+
+    /// Attempts to upgrade to a [`CompactRcn`](struct.CompactRcn.html), returning `None` if the value was dropped.
+    pub fn upgrade(&self) -> Option<CompactRcn<T, C>> {
+        if self.is_none() {
+            return None;
+        }
+        unsafe { set(&(*self.ptr).strong, count::increment(get(&(*self.ptr).strong), C::MAX)) };
+        Some(CompactRcn { ptr: self.ptr, phantom: PhantomData })
+    }
+
+    /// Creates another weak pointer to the same box.
+    pub fn share(&self) -> CompactWeakn<T, C> {
+        if !self.ptr.is_null() {
+            unsafe { set(&(*self.ptr).weak, count::increment(get(&(*self.ptr).weak), C::MAX)) };
+        }
+        CompactWeakn { ptr: self.ptr }
+    }
+}
+
+impl<T, C: Counter> Default for CompactWeakn<T, C> {
+    fn default() -> CompactWeakn<T, C> {
+        CompactWeakn::new()
+    }
+}
+
+impl<T: ?Sized, C: Counter> Drop for CompactWeakn<T, C> {
+    fn drop(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+        unsafe {
+            let weak = count::decrement(get(&(*self.ptr).weak), C::MAX, "weak");
+            set(&(*self.ptr).weak, weak);
+            if weak == 0 && get(&(*self.ptr).strong) == 0 {
+                deallocate(self.ptr);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> fmt::Debug for CompactWeakn<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(CompactWeakn)")
+    }
+}
+
+struct NoWeakBox<T: ?Sized, C> {
+    strong: Cell<C>,
+    value: T,
+}
+
+/// A single-threaded reference-counting pointer with none value, `C`-sized strong count and no weak support.
+///
+/// The box only stores the strong count, so a `RcnNoWeak<u32, u32>` box takes 8 bytes. Use it for values that
+/// never call `downgrade`.
+pub struct RcnNoWeak<T: ?Sized, C: Counter = u32> {
+    ptr: *mut NoWeakBox<T, C>,
+    phantom: PhantomData<T>,
+}
+
+impl<T, C: Counter> RcnNoWeak<T, C> {
+    /// Constructs a new `RcnNoWeak<T, C>`.
+    pub fn new(data: T) -> RcnNoWeak<T, C> {
+        let ptr = Box::into_raw(Box::new(NoWeakBox { strong: Cell::new(C::from_usize(1)), value: data }));
+        #[cfg(feature = "leak-check")]
+        unsafe {
+            crate::debug::register_counts::<T, C>(ptr, mem::size_of_val(&*ptr), &(*ptr).strong, ptr::null());
+        }
+        RcnNoWeak { ptr, phantom: PhantomData }
+    }
+
+    /// Constructs a `RcnNoWeak<T, C>` with none value.
+    pub fn none() -> RcnNoWeak<T, C> {
+        RcnNoWeak { ptr: ptr::null_mut(), phantom: PhantomData }
+    }
+
+    /// Takes the value out, leaving a none in its place. Returns `None` if the pointer is not unique.
+    pub fn take(&mut self) -> Option<T> {
+        if self.is_unique() {
+            unsafe {
+                let out_ptr = self.ptr;
+                self.ptr = ptr::null_mut();
+                let value = ptr::read(&(*out_ptr).value);
+                deallocate_no_weak(out_ptr);
+                Some(value)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained value if the pointer is unique, otherwise returns it back.
+    pub fn try_unwrap(mut this: Self) -> Result<T, Self> {
+        match this.take() {
+            Some(value) => Ok(value),
+            None => Err(this),
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> RcnNoWeak<T, C> {
+    /// Gets the number of strong pointers to this value.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        if self.ptr.is_null() { 0 } else { unsafe { get(&(*self.ptr).strong) } }
+    }
+
+    /// Returns `true` if `strong_count == 1`.
+    #[inline]
+    pub fn is_unique(&self) -> bool {
+        self.strong_count() == 1
+    }
+
+    /// Returns `true` if the current pointer is `None`.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.strong_count() == 0
+    }
+
+    /// Returns `true` if the current pointer is not `None`.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.strong_count() > 0
+    }
+
+    /// Returns true if the two pointers point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr, other.ptr)
+    }
+
+    /// Creates another pointer to the same value, increasing the strong count.
+    pub fn share(&self) -> RcnNoWeak<T, C> {
+        if self.is_some() {
+            unsafe { set(&(*self.ptr).strong, count::increment(self.strong_count(), C::MAX)) };
+            RcnNoWeak { ptr: self.ptr, phantom: PhantomData }
+        } else {
+            panic!("share of RcnNoWeak with none value");
+        }
+    }
+
+    /// Like [`share`](#method.share), but returns an error when the strong count is at the limit of `C`.
+    pub fn try_share(&self) -> Result<RcnNoWeak<T, C>, OverflowError> {
+        if self.is_some() {
+            let strong = count::try_increment(self.strong_count(), C::MAX)?;
+            unsafe { set(&(*self.ptr).strong, strong) };
+            Ok(RcnNoWeak { ptr: self.ptr, phantom: PhantomData })
+        } else {
+            panic!("share of RcnNoWeak with none value");
+        }
+    }
+}
+
+impl<T: Clone, C: Counter> RcnNoWeak<T, C> {
+    /// Get a clone of internal data.
+    pub fn get(&self) -> T {
+        (**self).clone()
+    }
+
+    /// Overwrites the shared value with a clone of `data`.
+    pub fn set(&mut self, data: &T) {
+        **self = data.clone();
+    }
+}
+
+unsafe fn deallocate_no_weak<T: ?Sized, C>(ptr: *mut NoWeakBox<T, C>) {
+    #[cfg(feature = "leak-check")]
+    crate::debug::unregister(ptr);
+    dealloc(ptr as *mut u8, Layout::for_value(&*ptr));
+}
+
+impl<T: ?Sized, C: Counter> Drop for RcnNoWeak<T, C> {
+    fn drop(&mut self) {
+        if self.is_some() {
+            unsafe {
+                let strong = count::decrement(self.strong_count(), C::MAX, "strong");
+                set(&(*self.ptr).strong, strong);
+                if strong == 0 {
+                    ptr::drop_in_place(&mut (*self.ptr).value);
+                    deallocate_no_weak(self.ptr);
+                }
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> Deref for RcnNoWeak<T, C> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        if self.is_some() {
+            unsafe { &(*self.ptr).value }
+        } else {
+            panic!("deref of none rcn!");
+        }
+    }
+}
+
+impl<T: ?Sized, C: Counter> DerefMut for RcnNoWeak<T, C> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        if self.is_some() {
+            unsafe { &mut (*self.ptr).value }
+        } else {
+            panic!("deref_mut of none rcn!");
+        }
+    }
+}
+
+impl<T: Default, C: Counter> Default for RcnNoWeak<T, C> {
+    fn default() -> RcnNoWeak<T, C> {
+        RcnNoWeak::new(Default::default())
+    }
+}
+
+impl<T, C: Counter> From<T> for RcnNoWeak<T, C> {
+    fn from(t: T) -> Self {
+        RcnNoWeak::new(t)
+    }
+}
+
+impl<T: ?Sized + fmt::Display, C: Counter> fmt::Display for RcnNoWeak<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, C: Counter> fmt::Debug for RcnNoWeak<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + PartialEq, C: Counter> PartialEq for RcnNoWeak<T, C> {
+    fn eq(&self, other: &RcnNoWeak<T, C>) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq, C: Counter> Eq for RcnNoWeak<T, C> {}
+
+impl<T: ?Sized + PartialOrd, C: Counter> PartialOrd for RcnNoWeak<T, C> {
+    fn partial_cmp(&self, other: &RcnNoWeak<T, C>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{OverflowPolicy, set_overflow_policy};
+    use std::mem::size_of;
+
+    #[test]
+    fn header_sizes() {
+        assert_eq!(size_of::<CompactBox<u32, u16>>(), 8);
+        assert_eq!(size_of::<CompactBox<u32, u32>>(), 12);
+        assert_eq!(size_of::<NoWeakBox<u32, u32>>(), 8);
+        assert_eq!(size_of::<CompactRcn<u32, u16>>(), size_of::<*const ()>());
+    }
+
+    #[test]
+    fn counts_and_weak() {
+        let a: CompactRcn<String, u16> = CompactRcn::new(String::from("a"));
+        let b = a.share();
+        let w = a.downgrade();
+        assert_eq!((b.strong_count(), b.weak_count()), (2, 1));
+        assert_eq!(*w.upgrade().unwrap(), "a");
+        drop(a);
+        drop(b);
+        assert!(w.is_none());
+        assert!(w.upgrade().is_none());
+
+        let mut c: CompactRcn<i32, u8> = CompactRcn::new(3);
+        assert_eq!(c.take(), Some(3));
+        assert!(c.is_none());
+        let d: CompactRcn<i32, u8> = CompactRcn::new(4);
+        let _w = d.downgrade();
+        assert_eq!(CompactRcn::try_unwrap(d), Ok(4));
+    }
+
+    #[test]
+    fn overflow_respects_width() {
+        let a: CompactRcn<i32, u8> = CompactRcn::new(1);
+        let shares: Vec<_> = (0..253).map(|_| a.share()).collect();
+        assert_eq!(a.strong_count(), 254);
+        assert_eq!(a.try_share().unwrap_err(), OverflowError);
+
+        set_overflow_policy(OverflowPolicy::Saturate);
+        let saturated = a.share();
+        set_overflow_policy(OverflowPolicy::Abort);
+        assert_eq!(a.strong_count(), 255);
+        drop(shares);
+        drop(saturated);
+        assert_eq!(a.strong_count(), 255);
+    }
+
+    #[test]
+    fn saturated_weak_count_survives_the_last_drop() {
+        let a: CompactRcn<String, u8> = CompactRcn::new(String::from("immortal"));
+        let weaks: Vec<_> = (0..254).map(|_| a.downgrade()).collect();
+        set_overflow_policy(OverflowPolicy::Saturate);
+        let saturated = a.downgrade();
+        set_overflow_policy(OverflowPolicy::Abort);
+        assert_eq!(a.weak_count(), 255);
+
+        drop(a);
+        assert!(saturated.is_none());
+        drop(weaks);
+        assert!(saturated.upgrade().is_none());
+    }
+
+    #[test]
+    fn no_weak() {
+        let a: RcnNoWeak<Vec<i32>, u32> = RcnNoWeak::new(vec![1, 2]);
+        let mut b = a.share();
+        assert!(!b.is_unique());
+        b.push(3);
+        assert_eq!(*a, vec![1, 2, 3]);
+        drop(a);
+        assert_eq!(RcnNoWeak::try_unwrap(b), Ok(vec![1, 2, 3]));
+        let none: RcnNoWeak<i32> = RcnNoWeak::none();
+        assert!(none.is_none() && none.strong_count() == 0);
+    }
+
+    #[test]
+    fn none_downgrade() {
+        let none: CompactRcn<i32, u16> = CompactRcn::none();
+        let w = none.downgrade();
+        assert!(w.is_none());
+        assert!(w.upgrade().is_none());
+        assert!(none.try_downgrade().unwrap().is_none());
+
+        let mut taken: CompactRcn<i32> = CompactRcn::new(1);
+        assert_eq!(taken.take(), Some(1));
+        assert!(taken.downgrade().share().upgrade().is_none());
+        assert_eq!(taken.weak_count(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Strong and weak count arithmetic, and what happens when a count overflows.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::process;
+
+/// What happens when a strong or weak count would overflow.
+///
+/// The policy is set per thread with [`set_overflow_policy`](fn.set_overflow_policy.html) and is only consulted
+/// when a count actually reaches its limit, so it costs nothing on the regular paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum OverflowPolicy {
+    /// Abort the process, like `std::rc::Rc` does. This is the default.
+    #[default]
+    Abort,
+    /// Saturate the count at its maximum. A saturated count is never decremented again, so the box becomes
+    /// immortal: its value is never dropped and its memory is never freed.
+    Saturate,
+}
+
+/// The error returned by [`Rcn::try_share`] and [`Rcn::try_downgrade`] when the count is at its limit.
+///
+/// [`Rcn::try_share`]: struct.Rcn.html#method.try_share
+/// [`Rcn::try_downgrade`]: struct.Rcn.html#method.try_downgrade
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OverflowError;
+
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "reference count overflow")
+    }
+}
+
+impl Error for OverflowError {}
+
+thread_local! {
+    static POLICY: Cell<OverflowPolicy> = const { Cell::new(OverflowPolicy::Abort) };
+}
+
+/// Sets the overflow policy of the current thread.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{OverflowPolicy, set_overflow_policy, overflow_policy};
+///
+/// assert_eq!(overflow_policy(), OverflowPolicy::Abort);
+/// set_overflow_policy(OverflowPolicy::Saturate);
+/// assert_eq!(overflow_policy(), OverflowPolicy::Saturate);
+/// ```
+pub fn set_overflow_policy(policy: OverflowPolicy) {
+    POLICY.with(|current| current.set(policy));
+}
+
+/// Returns the overflow policy of the current thread.
+pub fn overflow_policy() -> OverflowPolicy {
+    POLICY.try_with(Cell::get).unwrap_or_default()
+}
+
+/// Returns `count + 1`, applying the overflow policy if that would reach `max`. A saturated count stays
+/// saturated.
+///
+/// `max` itself is reserved for saturated counts, so a count that reaches it through saturation is
+/// recognized as immortal by [`decrement`].
+#[inline]
+pub(crate) fn increment(count: usize, max: usize) -> usize {
+    if count < max - 1 {
+        count + 1
+    } else if count == max {
+        max
+    } else {
+        overflow(max)
+    }
+}
+
+/// Returns `count + 1`, or an error if that would reach `max`.
+#[inline]
+pub(crate) fn try_increment(count: usize, max: usize) -> Result<usize, OverflowError> {
+    if count < max - 1 {
+        Ok(count + 1)
+    } else {
+        Err(OverflowError)
+    }
+}
+
+/// Returns `count - 1`. A saturated count stays saturated, and a count of zero is an invariant violation.
+#[inline]
+pub(crate) fn decrement(count: usize, max: usize, what: &str) -> usize {
+    if count == max {
+        max
+    } else if count == 0 {
+        invariant_violation(what)
+    } else {
+        count - 1
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn overflow(max: usize) -> usize {
+    match overflow_policy() {
+        OverflowPolicy::Abort => {
+            eprintln!("rcn: reference count overflow, aborting");
+            process::abort()
+        }
+        OverflowPolicy::Saturate => max,
+    }
+}
+
+/// Aborts the process: a count went below zero, so some handle was released twice and the box can no
+/// longer be trusted. Unwinding from here would let other handles keep using it.
+#[cold]
+#[inline(never)]
+fn invariant_violation(what: &str) -> ! {
+    eprintln!("rcn: {} count underflow, a handle was released twice; aborting", what);
+    process::abort()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Rcn;
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(increment(1, u16::MAX as usize), 2);
+        assert_eq!(increment(u16::MAX as usize, u16::MAX as usize), u16::MAX as usize);
+        assert_eq!(try_increment(u16::MAX as usize - 2, u16::MAX as usize), Ok(u16::MAX as usize - 1));
+        assert_eq!(try_increment(u16::MAX as usize - 1, u16::MAX as usize), Err(OverflowError));
+        assert_eq!(decrement(2, usize::MAX, "strong"), 1);
+        assert_eq!(decrement(usize::MAX, usize::MAX, "strong"), usize::MAX);
+    }
+
+    #[test]
+    fn try_share_at_limit() {
+        let a = Rcn::new(1);
+        unsafe { a.ptr.as_ref().strong.set(usize::MAX - 1) };
+        assert_eq!(a.try_share().unwrap_err(), OverflowError);
+        assert_eq!(a.strong_count(), usize::MAX - 1);
+        unsafe { a.ptr.as_ref().strong.set(1) };
+        let b = a.try_share().unwrap();
+        assert_eq!(b.strong_count(), 2);
+
+        unsafe { a.ptr.as_ref().set_weak_count(crate::WEAK_MAX - 1) };
+        assert!(a.try_downgrade().is_err());
+        unsafe { a.ptr.as_ref().set_weak_count(0) };
+    }
+
+    #[test]
+    fn saturated_box_is_immortal() {
+        set_overflow_policy(OverflowPolicy::Saturate);
+        let a = Rcn::new(String::from("immortal"));
+        unsafe { a.ptr.as_ref().strong.set(usize::MAX - 1) };
+        let b = a.share();
+        assert_eq!(b.strong_count(), usize::MAX);
+        let w = b.downgrade();
+        drop(a);
+        drop(b);
+        assert_eq!(w.upgrade().unwrap().strong_count(), usize::MAX);
+        assert_eq!(*w.upgrade().unwrap(), "immortal");
+        set_overflow_policy(OverflowPolicy::Abort);
+    }
+}
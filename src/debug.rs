@@ -0,0 +1,43 @@
+//! Debugging aids for `Rcn` and `Weakn`.
+//!
+//! * `leak-check`: every `RcnBox` allocated on the current thread is recorded in a thread-local registry
+//!   together with its type name, size and a view of its strong/weak counts. The record is removed when the
+//!   box memory is released, so anything still listed is either in use or leaked (cycles, forgotten
+//!   pointers, ...).
+//! * `track-holders`: every strong and weak handle remembers where it was created, see [`Rcn::holders`].
+//!
+//! [`Rcn::holders`]: ../struct.Rcn.html#method.holders
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate rcn;
+//! # #[cfg(feature = "leak-check")]
+//! # {
+//! use rcn::{debug, Rcn};
+//!
+//! let before = debug::snapshot();
+//! let kept = Rcn::new(10);
+//! let dropped = Rcn::new(20);
+//! drop(dropped);
+//!
+//! let alive = before.live_since();
+//! assert_eq!(alive.len(), 1);
+//! assert_eq!(alive[0].strong, kept.strong_count());
+//! # }
+//! ```
+
+#[cfg(feature = "leak-check")]
+mod leaks;
+#[cfg(feature = "track-holders")]
+mod holders;
+
+#[cfg(feature = "leak-check")]
+pub use self::leaks::{Allocation, Snapshot, snapshot, live_allocations, live_count, report, assert_no_leaks_since};
+#[cfg(feature = "leak-check")]
+pub(crate) use self::leaks::{register, register_counts, unregister};
+
+#[cfg(feature = "track-holders")]
+pub use self::holders::{Holder, HolderKind, capture_backtraces};
+#[cfg(feature = "track-holders")]
+pub(crate) use self::holders::{track, untrack, forget_box, holders_of};
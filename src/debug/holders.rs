@@ -0,0 +1,154 @@
+//! Creation sites of strong and weak handles (requires the `track-holders` feature).
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::panic::Location;
+use std::rc::Rc;
+
+/// The kind of handle recorded by a [`Holder`](struct.Holder.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HolderKind {
+    /// A `Rcn` handle.
+    Strong,
+    /// A `Weakn` handle.
+    Weak,
+}
+
+/// An outstanding handle to a box and the place where it was created.
+#[derive(Clone, Debug)]
+pub struct Holder {
+    /// Sequence number of the handle on this thread.
+    pub id: u64,
+    /// Whether the handle is strong or weak.
+    pub kind: HolderKind,
+    /// Source location of the `new`/`share`/`upgrade`/`downgrade` call that created the handle.
+    pub location: &'static Location<'static>,
+    /// Backtrace of the creation, if enabled with [`capture_backtraces`](fn.capture_backtraces.html).
+    pub backtrace: Option<Rc<Backtrace>>,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} #{} created at {}", self.kind, self.id, self.location)
+    }
+}
+
+#[derive(Default)]
+struct Table {
+    next_id: u64,
+    boxes: HashMap<usize, BTreeMap<u64, Holder>>,
+}
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table { next_id: 1, boxes: HashMap::new() });
+    static BACKTRACES: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Enables or disables backtrace capture for handles created on the current thread.
+///
+/// Backtraces are expensive, so they are off by default and only the source location is recorded.
+pub fn capture_backtraces(enabled: bool) {
+    BACKTRACES.with(|flag| flag.set(enabled));
+}
+
+/// Records a new handle to the box at `address` and returns its id.
+pub(crate) fn track(address: usize, kind: HolderKind, location: &'static Location<'static>) -> u64 {
+    let backtrace = if BACKTRACES.with(Cell::get) {
+        Some(Rc::new(Backtrace::force_capture()))
+    } else {
+        None
+    };
+    TABLE.try_with(|table| {
+        let mut table = table.borrow_mut();
+        let id = table.next_id;
+        table.next_id += 1;
+        table.boxes.entry(address).or_default().insert(id, Holder { id, kind, location, backtrace });
+        id
+    }).unwrap_or(0)
+}
+
+/// Removes the record of the handle `id` to the box at `address`.
+pub(crate) fn untrack(address: usize, id: u64) {
+    if id == 0 {
+        return;
+    }
+    let _ = TABLE.try_with(|table| {
+        let mut table = table.borrow_mut();
+        if let Some(holders) = table.boxes.get_mut(&address) {
+            holders.remove(&id);
+            if holders.is_empty() {
+                table.boxes.remove(&address);
+            }
+        }
+    });
+}
+
+/// Removes every record of the box at `address`, called when the box memory is released.
+pub(crate) fn forget_box(address: usize) {
+    let _ = TABLE.try_with(|table| {
+        table.borrow_mut().boxes.remove(&address);
+    });
+}
+
+/// Returns the outstanding handles to the box at `address`, oldest first.
+pub(crate) fn holders_of(address: usize) -> Vec<Holder> {
+    TABLE.with(|table| {
+        table.borrow().boxes.get(&address)
+            .map(|holders| holders.values().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Rcn;
+
+    #[test]
+    fn holders_follow_handles() {
+        let a = Rcn::new(1);
+        let line = line!() - 1;
+        let b = a.share();
+        let w = a.downgrade();
+
+        let holders = a.holders();
+        assert_eq!(holders.len(), 3);
+        assert_eq!(holders[0].kind, HolderKind::Strong);
+        assert_eq!(holders[0].location.line(), line);
+        assert_eq!(holders[0].location.file(), file!());
+        assert_eq!(holders[1].location.line(), line + 2);
+        assert_eq!(holders[2].kind, HolderKind::Weak);
+
+        drop(b);
+        let u = w.upgrade().unwrap();
+        let holders = a.holders();
+        assert_eq!(holders.len(), 3);
+        assert_eq!(holders[2].location.line(), line!() - 3);
+        drop(u);
+        drop(w);
+        assert_eq!(a.holders().len(), 1);
+    }
+
+    #[test]
+    fn backtraces_are_optional() {
+        let a = Rcn::new(1);
+        assert!(a.holders()[0].backtrace.is_none());
+        capture_backtraces(true);
+        let b = a.share();
+        capture_backtraces(false);
+        assert!(a.holders()[1].backtrace.is_some());
+        drop(b);
+    }
+
+    #[test]
+    fn forgotten_handle_is_listed() {
+        let a = Rcn::new(String::from("stray"));
+        let raw = Rcn::into_raw(a.share());
+        let holders = a.holders();
+        assert_eq!(holders.len(), 2);
+        assert!(format!("{}", holders[1]).starts_with("Strong #"));
+        assert!(!raw.is_null());
+    }
+}
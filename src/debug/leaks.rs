@@ -0,0 +1,297 @@
+//! Registry of live `Rcn` allocations (requires the `leak-check` feature). The boxes of `CompactRcn`,
+//! `RcnNoWeak` and `ThinRcn` are recorded too.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::compact::Counter;
+use crate::{RcnBox, WEAK_MAX};
+
+struct Entry {
+    id: u64,
+    type_name: &'static str,
+    size: usize,
+    strong: *const (),
+    /// Null for boxes without weak count.
+    weak: *const (),
+    /// Read the counts, whatever their width.
+    read_strong: unsafe fn(*const ()) -> usize,
+    read_weak: unsafe fn(*const ()) -> usize,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    live: HashMap<usize, Entry>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+unsafe fn read<C: Counter>(cell: *const ()) -> usize {
+    (*(cell as *const Cell<C>)).get().to_usize()
+}
+
+/// Reads the weak count of an `RcnBox`, leaving out the flags sharing its word.
+unsafe fn read_weak(cell: *const ()) -> usize {
+    (*(cell as *const Cell<usize>)).get() & WEAK_MAX
+}
+
+pub(crate) fn register<T: ?Sized>(ptr: *const RcnBox<T>, size: usize) {
+    unsafe { insert::<T>(ptr as *const (), size, &(*ptr).strong as *const _ as *const (), read::<usize>,
+                         &(*ptr).weak as *const _ as *const (), read_weak) };
+}
+
+/// Records a box of any pointer kind whose counts are `C`s. `weak` is null if the box has no weak count.
+pub(crate) fn register_counts<T: ?Sized, C: Counter>(ptr: *const impl ?Sized, size: usize, strong: *const Cell<C>,
+                                                      weak: *const Cell<C>) {
+    insert::<T>(ptr as *const (), size, strong as *const (), read::<C>, weak as *const (), read::<C>);
+}
+
+fn insert<T: ?Sized>(ptr: *const (), size: usize, strong: *const (), read_strong: unsafe fn(*const ()) -> usize,
+                     weak: *const (), read_weak: unsafe fn(*const ()) -> usize) {
+    let _ = REGISTRY.try_with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        let entry = Entry { id, type_name: std::any::type_name::<T>(), size, strong, weak, read_strong, read_weak };
+        registry.live.insert(ptr as usize, entry);
+    });
+}
+
+pub(crate) fn unregister<P: ?Sized>(ptr: *const P) {
+    let _ = REGISTRY.try_with(|registry| {
+        registry.borrow_mut().live.remove(&(ptr as *const () as usize));
+    });
+}
+
+/// A live `Rcn` allocation, as recorded by the registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Allocation {
+    /// Sequence number of the allocation on this thread.
+    pub id: u64,
+    /// Address of the `RcnBox`.
+    pub address: usize,
+    /// Name of the payload type.
+    pub type_name: &'static str,
+    /// Size of the `RcnBox` in bytes, header included.
+    pub size: usize,
+    /// Strong count at the time the allocation was listed.
+    pub strong: usize,
+    /// Weak count at the time the allocation was listed.
+    pub weak: usize,
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {} at {:#x} ({} bytes, strong = {}, weak = {})",
+            self.id, self.type_name, self.address, self.size, self.strong, self.weak)
+    }
+}
+
+/// A point in the allocation history of the current thread, see [`snapshot`].
+///
+/// [`snapshot`]: fn.snapshot.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Snapshot {
+    mark: u64,
+}
+
+impl Snapshot {
+    /// Returns the allocations made after this snapshot that are still alive.
+    pub fn live_since(&self) -> Vec<Allocation> {
+        collect(|id| id >= self.mark)
+    }
+
+    /// Returns the allocations made between this snapshot and `later` that are still alive.
+    pub fn live_between(&self, later: &Snapshot) -> Vec<Allocation> {
+        collect(|id| id >= self.mark && id < later.mark)
+    }
+}
+
+/// Takes a snapshot of the allocation history of the current thread.
+pub fn snapshot() -> Snapshot {
+    let mark = REGISTRY.with(|registry| registry.borrow().next_id);
+    Snapshot { mark }
+}
+
+/// Returns every live `Rcn` allocation of the current thread, oldest first.
+pub fn live_allocations() -> Vec<Allocation> {
+    collect(|_| true)
+}
+
+/// Returns the number of live `Rcn` allocations of the current thread.
+pub fn live_count() -> usize {
+    REGISTRY.with(|registry| registry.borrow().live.len())
+}
+
+/// Formats `allocations` as a multi-line report, one allocation per line.
+pub fn report(allocations: &[Allocation]) -> String {
+    let mut out = String::new();
+    for allocation in allocations {
+        out.push_str(&format!("  {}\n", allocation));
+    }
+    out
+}
+
+/// Panics with a report if any allocation made after `snapshot` is still alive.
+///
+/// This is the function behind [`assert_no_leaks!`](../macro.assert_no_leaks.html).
+#[track_caller]
+pub fn assert_no_leaks_since(snapshot: &Snapshot) {
+    let leaked = snapshot.live_since();
+    if !leaked.is_empty() {
+        panic!("{} Rcn allocation(s) leaked:\n{}", leaked.len(), report(&leaked));
+    }
+}
+
+fn collect<F: Fn(u64) -> bool>(filter: F) -> Vec<Allocation> {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        let mut out: Vec<Allocation> = registry.live.iter()
+            .filter(|(_, entry)| filter(entry.id))
+            .map(|(&address, entry)| unsafe {
+                Allocation {
+                    id: entry.id,
+                    address,
+                    type_name: entry.type_name,
+                    size: entry.size,
+                    strong: (entry.read_strong)(entry.strong),
+                    weak: if entry.weak.is_null() { 0 } else { (entry.read_weak)(entry.weak) },
+                }
+            })
+            .collect();
+        out.sort_by_key(|allocation| allocation.id);
+        out
+    })
+}
+
+/// Runs the given block and panics if any `Rcn` allocated inside it is still alive afterwards.
+///
+/// Evaluates to the value of the block. Requires the `leak-check` feature.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate rcn;
+/// use rcn::Rcn;
+///
+/// # fn main() {
+/// let sum = assert_no_leaks!({
+///     let a = Rcn::new(1);
+///     let b = a.share();
+///     *a + *b
+/// });
+/// assert_eq!(sum, 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_no_leaks {
+    ($body:block) => {{
+        let snapshot = $crate::debug::snapshot();
+        let result = $body;
+        $crate::debug::assert_no_leaks_since(&snapshot);
+        result
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Rcn, Weakn};
+    use std::cell::RefCell;
+
+    #[test]
+    fn registry_tracks_counts() {
+        let before = snapshot();
+        let a = Rcn::new(String::from("a"));
+        let b = a.share();
+        let w = a.downgrade();
+
+        let live = before.live_since();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].strong, 2);
+        assert_eq!(live[0].weak, 1);
+        assert!(live[0].type_name.ends_with("String"));
+
+        drop(a);
+        drop(b);
+        assert_eq!(before.live_since()[0].strong, 0);
+        drop(w);
+        assert!(before.live_since().is_empty());
+    }
+
+    #[test]
+    fn compact_and_thin_boxes_are_recorded() {
+        use crate::compact::{CompactRcn, RcnNoWeak};
+        use crate::ThinRcn;
+
+        let before = snapshot();
+        let compact: CompactRcn<u32, u8> = CompactRcn::new(1);
+        let weak = compact.downgrade();
+        let no_weak: RcnNoWeak<u32, u16> = RcnNoWeak::new(2);
+        let shared = no_weak.share();
+        let thin: ThinRcn<str> = ThinRcn::from("thin");
+
+        let live = before.live_since();
+        assert_eq!(live.iter().map(|a| (a.strong, a.weak)).collect::<Vec<_>>(), [(1, 1), (2, 0), (1, 0)]);
+        assert!(live[2].type_name.ends_with("str"));
+        drop((compact, no_weak, shared, thin));
+        assert_eq!(before.live_since().len(), 1);
+        drop(weak);
+        assert!(before.live_since().is_empty());
+    }
+
+    #[test]
+    fn snapshot_between() {
+        let first = snapshot();
+        let a = Rcn::new(1);
+        let second = snapshot();
+        let b = Rcn::new(2);
+
+        assert_eq!(first.live_between(&second).len(), 1);
+        assert_eq!(second.live_since().len(), 1);
+        drop(a);
+        assert!(first.live_between(&second).is_empty());
+        drop(b);
+    }
+
+    #[test]
+    fn try_unwrap_and_take_release_box() {
+        assert_no_leaks!({
+            let x = Rcn::new(5);
+            let w = x.downgrade();
+            assert_eq!(Rcn::try_unwrap(x), Ok(5));
+            drop(w);
+
+            let mut y = Rcn::new(6);
+            assert_eq!(y.take(), Some(6));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "1 Rcn allocation(s) leaked")]
+    fn cycle_is_reported() {
+        struct Node {
+            next: RefCell<Option<Rcn<Node>>>,
+        }
+        assert_no_leaks!({
+            let a = Rcn::new(Node { next: RefCell::new(None) });
+            *a.next.borrow_mut() = Some(a.share());
+        });
+    }
+
+    #[test]
+    fn weak_self_reference_does_not_leak() {
+        struct Node {
+            me: RefCell<Weakn<Node>>,
+        }
+        assert_no_leaks!({
+            let a = Rcn::new(Node { me: RefCell::new(Weakn::new()) });
+            *a.me.borrow_mut() = a.downgrade();
+        });
+    }
+}
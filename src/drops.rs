@@ -0,0 +1,261 @@
+//! Non-recursive destruction of values, and deferred release of box memory.
+//!
+//! When the last strong handle to a box drops while another value is already being dropped on this thread,
+//! the box is queued instead of dropped in place, and the outermost drop drains the queue in a loop, first
+//! in first out. Long chains like linked lists are then dropped with constant stack depth.
+
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use crate::count;
+use crate::hooks;
+use crate::{RcnBox, WEAK_MAX};
+
+/// A box whose strong count reached zero, with its pointer erased. Fat pointers take two words.
+struct Pending {
+    raw: [usize; 2],
+    finish: unsafe fn([usize; 2]),
+}
+
+struct Frees {
+    boxes: Vec<(*mut u8, Layout)>,
+}
+
+impl Drop for Frees {
+    fn drop(&mut self) {
+        for (ptr, layout) in self.boxes.drain(..) {
+            unsafe { free_now(ptr, layout) };
+        }
+    }
+}
+
+thread_local! {
+    static DROPPING: Cell<bool> = const { Cell::new(false) };
+    static PENDING: RefCell<VecDeque<Pending>> = const { RefCell::new(VecDeque::new()) };
+    static DEFERRING: Cell<usize> = const { Cell::new(0) };
+    static FREES: RefCell<Frees> = const { RefCell::new(Frees { boxes: Vec::new() }) };
+}
+
+/// Drops the value of a box whose strong count just reached zero, and frees the box if no weak
+/// references remain. Called from a drop that is already in progress, it only queues the box.
+pub(crate) unsafe fn release<T: ?Sized>(ptr: NonNull<RcnBox<T>>) {
+    // Hold a weak reference until the value is dropped, so neither a `Weakn` to this box stored inside the
+    // value nor one dropped while the box is queued can free it under us.
+    let header = ptr.as_ref();
+    header.set_weak_count(count::increment(header.weak_count(), WEAK_MAX));
+    let nested = DROPPING.try_with(|dropping| dropping.replace(true)).unwrap_or(false);
+    if nested {
+        // On thread exit the queue may be gone; dropping in place is the only option left.
+        if PENDING.try_with(|queue| queue.borrow_mut().push_back(erase(ptr))).is_err() {
+            drop_value(ptr);
+        }
+        return;
+    }
+    let _drain = Drain;
+    drop_value(ptr);
+}
+
+/// Drains the queue when the outermost drop ends, also when it unwinds.
+struct Drain;
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        while let Some(pending) = PENDING.try_with(|queue| queue.borrow_mut().pop_front()).ok().flatten() {
+            unsafe { (pending.finish)(pending.raw) };
+        }
+        let _ = DROPPING.try_with(|dropping| dropping.set(false));
+    }
+}
+
+fn erase<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> Pending {
+    assert!(mem::size_of::<NonNull<RcnBox<T>>>() <= mem::size_of::<[usize; 2]>());
+    let mut raw = [0; 2];
+    unsafe { ptr::write(raw.as_mut_ptr() as *mut NonNull<RcnBox<T>>, ptr) };
+    Pending { raw, finish: finish::<T> }
+}
+
+unsafe fn finish<T: ?Sized>(raw: [usize; 2]) {
+    drop_value(ptr::read(raw.as_ptr() as *const NonNull<RcnBox<T>>));
+}
+
+/// Drops the value of a box, then gives up the weak reference taken by `release`.
+unsafe fn drop_value<T: ?Sized>(ptr: NonNull<RcnBox<T>>) {
+    let ptr = ptr.as_ptr();
+    hooks::drop_value(ptr);
+    let weak = count::decrement((*ptr).weak_count(), WEAK_MAX, "weak");
+    (*ptr).set_weak_count(weak);
+    if weak == 0 {
+        RcnBox::deallocate(ptr);
+    }
+}
+
+/// Returns the memory of a box to the allocator, or queues it inside [`defer_drops`].
+pub(crate) unsafe fn free(ptr: *mut u8, layout: Layout) {
+    if DEFERRING.try_with(Cell::get).unwrap_or(0) > 0 {
+        let queued = FREES.try_with(|frees| frees.borrow_mut().boxes.push((ptr, layout)));
+        if queued.is_ok() {
+            return;
+        }
+    }
+    free_now(ptr, layout);
+}
+
+unsafe fn free_now(ptr: *mut u8, layout: Layout) {
+    #[cfg(feature = "checked")]
+    crate::checked::quarantine(ptr, layout);
+    #[cfg(not(feature = "checked"))]
+    std::alloc::dealloc(ptr, layout);
+}
+
+/// Runs `f`, keeping the memory of every box released meanwhile on this thread until
+/// [`flush_deferred`](fn.flush_deferred.html) is called, so latency-sensitive code can return it to the
+/// allocator at a quiet point. Calls can be nested.
+///
+/// Values are still dropped as soon as their last strong handle goes, since they may borrow data that
+/// does not live past `f`; only the release of the box memory is deferred.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, defer_drops, flush_deferred};
+///
+/// let particles = defer_drops(|| {
+///     let particles: Vec<Rcn<[f32; 3]>> = (0..100).map(|_| Rcn::new([0.0; 3])).collect();
+///     particles.len()
+/// });
+/// assert_eq!(particles, 100);
+/// assert_eq!(flush_deferred(), 100);
+/// ```
+pub fn defer_drops<R, F: FnOnce() -> R>(f: F) -> R {
+    struct Deferring;
+
+    impl Drop for Deferring {
+        fn drop(&mut self) {
+            DEFERRING.with(|deferring| deferring.set(deferring.get() - 1));
+        }
+    }
+
+    DEFERRING.with(|deferring| deferring.set(deferring.get() + 1));
+    let _deferring = Deferring;
+    f()
+}
+
+/// Returns the memory of every box deferred by [`defer_drops`](fn.defer_drops.html) on this thread to the
+/// allocator, and returns how many there were.
+pub fn flush_deferred() -> usize {
+    let boxes = FREES.try_with(|frees| mem::take(&mut frees.borrow_mut().boxes)).unwrap_or_default();
+    let count = boxes.len();
+    for (ptr, layout) in boxes {
+        unsafe { free_now(ptr, layout) };
+    }
+    count
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Rcn, Weakn};
+
+    struct Node {
+        next: Rcn<Node>,
+    }
+
+    #[test]
+    fn long_chains_drop_iteratively() {
+        let mut head = Rcn::none();
+        for _ in 0..100_000 {
+            head = Rcn::new(Node { next: head });
+        }
+        let tail = {
+            let mut node = head.share();
+            while node.next.is_some() {
+                node = node.next.share();
+            }
+            node.downgrade()
+        };
+        drop(head);
+        assert!(tail.upgrade().is_none());
+    }
+
+    #[test]
+    fn nested_values_drop_after_their_parent_in_release_order() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Parent {
+            log: Rc<RefCell<Vec<&'static str>>>,
+            first: Rcn<Child>,
+            second: Rcn<Child>,
+        }
+
+        struct Child(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+        impl Drop for Parent {
+            fn drop(&mut self) {
+                self.second = Rcn::none();
+                assert!(self.first.is_some());
+                self.log.borrow_mut().push("parent");
+            }
+        }
+
+        impl Drop for Child {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let first = Rcn::new(Child("first", log.clone()));
+        let second = Rcn::new(Child("second", log.clone()));
+        drop(Rcn::new(Parent { log: log.clone(), first, second }));
+        assert_eq!(*log.borrow(), ["parent", "second", "first"]);
+    }
+
+    #[test]
+    fn queued_boxes_outlive_their_weak_handles() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Logged(Rc<RefCell<Vec<&'static str>>>);
+
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.0.borrow_mut().push("dropped");
+            }
+        }
+
+        #[allow(dead_code)]
+        struct Holder {
+            last: Rcn<Logged>,
+            weak: Weakn<Logged>,
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let last = Rcn::new(Logged(log.clone()));
+        let l = log.clone();
+        last.on_free(move || l.borrow_mut().push("freed"));
+        // Dropping the holder queues the release of `last`, then drops the only weak handle to it.
+        let holder = Rcn::new(Holder { weak: last.downgrade(), last });
+        drop(holder);
+        assert_eq!(*log.borrow(), ["dropped", "freed"]);
+    }
+
+    #[test]
+    fn frees_wait_for_flush() {
+        let weak: Weakn<String> = defer_drops(|| {
+            let a = Rcn::new(String::from("deferred"));
+            let w = a.downgrade();
+            drop(Rcn::new(1u8));
+            drop(a);
+            w
+        });
+        assert!(weak.upgrade().is_none());
+        assert_eq!(flush_deferred(), 1);
+        drop(weak);
+        assert_eq!(flush_deferred(), 0);
+    }
+}
@@ -0,0 +1,181 @@
+//! `FrozenRcn<T>`: a handle to a frozen value, without mutating methods.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::{Rcn, Weakn};
+
+/// A shared handle to a [frozen](struct.Rcn.html#method.freeze) value, returned by
+/// [`Rcn::into_frozen`](struct.Rcn.html#method.into_frozen).
+///
+/// It derefs to the value but has no `DerefMut`, `set`, `replace` or `take`, so code holding one cannot even
+/// attempt a mutation. The value is also frozen at runtime, so other `Rcn`s to it cannot change it either.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{FrozenRcn, Rcn};
+///
+/// struct Config {
+///     threads: usize,
+/// }
+///
+/// fn load_plugin(config: FrozenRcn<Config>) -> usize {
+///     config.threads
+/// }
+///
+/// let config = Rcn::new(Config { threads: 4 });
+/// let frozen = Rcn::into_frozen(config.share());
+/// assert_eq!(load_plugin(frozen.share()), 4);
+/// assert!(config.is_frozen());
+/// ```
+pub struct FrozenRcn<T: ?Sized> {
+    rcn: Rcn<T>,
+}
+
+impl<T: ?Sized> FrozenRcn<T> {
+    /// Wraps `rcn`, which must be frozen.
+    pub(crate) fn new(rcn: Rcn<T>) -> FrozenRcn<T> {
+        debug_assert!(rcn.is_frozen());
+        FrozenRcn { rcn }
+    }
+
+    /// Makes another handle to the same value.
+    #[inline]
+    #[track_caller]
+    pub fn share(&self) -> FrozenRcn<T> {
+        FrozenRcn { rcn: self.rcn.share() }
+    }
+
+    /// Creates a weak handle to the value. The value stays frozen, so it cannot be changed through the
+    /// `Rcn` it upgrades to either.
+    #[inline]
+    #[track_caller]
+    pub fn downgrade(&self) -> Weakn<T> {
+        self.rcn.downgrade()
+    }
+
+    /// Returns a plain `Rcn` to the value, which is still frozen.
+    #[inline]
+    pub fn into_rcn(this: Self) -> Rcn<T> {
+        this.rcn
+    }
+
+    /// Gets the number of strong pointers to the value.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        self.rcn.strong_count()
+    }
+
+    /// Gets the number of weak pointers to the value.
+    #[inline]
+    pub fn weak_count(&self) -> usize {
+        self.rcn.weak_count()
+    }
+
+    /// Returns `true` if both handles point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rcn::ptr_eq(&this.rcn, &other.rcn)
+    }
+}
+
+impl<T: ?Sized> Deref for FrozenRcn<T> {
+    type Target = T;
+
+    #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &T {
+        &self.rcn
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for FrozenRcn<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for FrozenRcn<T> {
+    fn eq(&self, other: &FrozenRcn<T>) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for FrozenRcn<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for FrozenRcn<T> {
+    fn partial_cmp(&self, other: &FrozenRcn<T>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for FrozenRcn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for FrozenRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for FrozenRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn frozen_values_refuse_mutation() {
+        let mut a = Rcn::new(String::from("fixed"));
+        let mut b = a.share();
+        assert!(!a.is_frozen());
+        let weak = a.downgrade();
+        b.freeze();
+        assert!(a.is_frozen());
+        // The flag shares the word of the weak count without changing it.
+        assert_eq!(a.weak_count(), 1);
+        drop(weak);
+        assert_eq!(a.weak_count(), 0);
+        assert!(a.is_frozen());
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| a.push('!'))).is_err());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| a.set(&String::new()))).is_err());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| a.replace(String::new()))).is_err());
+        drop(b.share());
+        drop(a);
+        assert_eq!(b.take(), None);
+        let b = Rcn::try_unwrap(b).unwrap_err();
+        let b = Rcn::try_into_unique(b).unwrap_err();
+        assert_eq!(*b, "fixed");
+    }
+
+    #[test]
+    fn frozen_handles_and_aliases() {
+        let mut pair = Rcn::new((1, String::from("one")));
+        let mut name = Rcn::alias(&mut pair, |p| &mut p.1);
+        let frozen = Rcn::into_frozen(pair);
+        assert!(name.is_frozen());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| name.clear())).is_err());
+        assert_eq!(frozen.0, 1);
+        assert_eq!(frozen.weak_count(), 0);
+        let rcn = FrozenRcn::into_rcn(frozen.share());
+        assert!(rcn.is_frozen());
+        assert!(FrozenRcn::ptr_eq(&frozen, &FrozenRcn::new(rcn)));
+
+        // The flag goes with the box, not with its address.
+        drop((frozen, name));
+        assert!(!Rcn::new((2, String::new())).is_frozen());
+    }
+}
@@ -0,0 +1,298 @@
+//! `HashedRcn<T>`: a shared value that caches its hash with its box.
+
+use std::cell::RefCell;
+use std::collections::hash_map::{DefaultHasher, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+
+use crate::alias;
+use crate::{Rcn, RcnBox, HASHED};
+
+thread_local! {
+    /// Cached hashes by header address, for the boxes flagged with `HASHED`.
+    static HASHES: RefCell<HashMap<usize, u64>> = RefCell::new(HashMap::new());
+}
+
+/// Caches `hash` for the value of the box at `header`.
+fn store(header: *mut RcnBox<()>, hash: u64) {
+    HASHES.with(|hashes| hashes.borrow_mut().insert(header as usize, hash));
+    unsafe { (*header).set_flag(HASHED, true) };
+}
+
+/// Returns the cached hash of the box at `header`. Boxes that were never hashed skip the lookup.
+fn cached(header: *mut RcnBox<()>) -> Option<u64> {
+    if !unsafe { (*header).has_flag(HASHED) } {
+        return None;
+    }
+    HASHES.with(|hashes| hashes.borrow().get(&(header as usize)).copied())
+}
+
+/// Drops the cached hash of the box at `header`, whose value changes or goes away.
+pub(crate) fn forget(header: *mut RcnBox<()>) {
+    let _ = HASHES.try_with(|hashes| hashes.borrow_mut().remove(&(header as usize)));
+    unsafe { (*header).set_flag(HASHED, false) };
+}
+
+fn hash_of<T: ?Sized + Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A reference-counting pointer that caches the hash of its value with the box.
+///
+/// A `HashedRcn<T>` is an `Rcn<T>` whose hash is computed once and kept for the box, behind a flag in its
+/// header and a per-thread side table, so other `Rcn`s pay nothing for it and `T` can be unsized. The
+/// hash is fed to hashers as a single `u64`, so large shared keys are not hashed again on every map
+/// lookup. Equality is decided by pointer identity first, then by the hashes, and only then by comparing
+/// the values.
+///
+/// The cached hash belongs to the box, not the handle: it survives [`into_rcn`](#method.into_rcn) and
+/// `From<Rcn<T>>`, and every write to the value drops it, whether it goes through this handle or a plain
+/// `Rcn` to the same box. It is then recomputed on next use. Handles made by
+/// [`Rcn::project`](struct.Rcn.html#method.project) point into a box they do not own alone and are
+/// hashed on every use. The cached hash uses a fixed hash function, so a `HashedRcn` cannot be looked up
+/// by `&T` in a map keyed by `HashedRcn<T>`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::HashedRcn;
+/// use std::collections::HashSet;
+///
+/// let path = HashedRcn::new(String::from("/usr/share/dict/words"));
+/// let mut seen = HashSet::new();
+/// seen.insert(path.share());
+/// assert!(seen.contains(&path));
+/// assert!(seen.contains(&HashedRcn::new(String::from("/usr/share/dict/words"))));
+/// ```
+pub struct HashedRcn<T: ?Sized> {
+    rcn: Rcn<T>,
+}
+
+impl<T: Hash> HashedRcn<T> {
+    /// Constructs a new `HashedRcn<T>`, hashing `value`.
+    pub fn new(value: T) -> HashedRcn<T> {
+        let hashed = HashedRcn { rcn: Rcn::new(value) };
+        hashed.hash_value();
+        hashed
+    }
+
+    /// Constructs a `HashedRcn<T>` with none value.
+    pub fn none() -> HashedRcn<T> {
+        HashedRcn { rcn: Rcn::none() }
+    }
+}
+
+impl<T: ?Sized + Hash> HashedRcn<T> {
+    /// Returns the hash of the value, computing and caching it if the value changed since it was last
+    /// hashed. A none `HashedRcn` hashes to 0.
+    pub fn hash_value(&self) -> u64 {
+        if self.rcn.is_none() {
+            return 0;
+        }
+        if alias::is_alias(self.rcn.ptr) {
+            return hash_of(&*self.rcn);
+        }
+        let header = alias::header(self.rcn.ptr);
+        cached(header).unwrap_or_else(|| {
+            let hash = hash_of(&*self.rcn);
+            store(header, hash);
+            hash
+        })
+    }
+}
+
+impl<T: ?Sized> HashedRcn<T> {
+    /// Returns `true` if the box holds a cached hash, that is if the value did not change since it was
+    /// last hashed.
+    pub fn is_hash_cached(&self) -> bool {
+        self.rcn.is_some() && cached(alias::header(self.rcn.ptr)).is_some()
+    }
+
+    /// Makes another pointer to the same box, sharing the cached hash.
+    #[inline]
+    pub fn share(&self) -> HashedRcn<T> {
+        HashedRcn { rcn: self.rcn.share() }
+    }
+
+    /// Returns the underlying `Rcn<T>`. The box keeps its cached hash until the value is written.
+    #[inline]
+    pub fn into_rcn(this: Self) -> Rcn<T> {
+        this.rcn
+    }
+
+    /// Returns `true` if both pointers point to the same box.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rcn::ptr_eq(&this.rcn, &other.rcn)
+    }
+
+    /// Gets the number of strong pointers to this value.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        self.rcn.strong_count()
+    }
+
+    /// Returns `true` if the pointer has none value.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.rcn.is_none()
+    }
+
+    /// Returns `true` if the pointer has some value.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.rcn.is_some()
+    }
+}
+
+impl<T: Clone + Hash> HashedRcn<T> {
+    /// Gets a clone of the value.
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn get(&self) -> T {
+        self.deref().clone()
+    }
+
+    /// Replaces the value with a clone of `data` and recomputes the hash.
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn set(&mut self, data: &T) {
+        if self.rcn.is_none() {
+            panic!("write (set) in none rcn!\n \t help: Use HashedRcn:new(...) to none pointers");
+        }
+        self.rcn.set(data);
+        self.hash_value();
+    }
+}
+
+impl<T: ?Sized> Deref for HashedRcn<T> {
+    type Target = T;
+
+    #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &T {
+        &self.rcn
+    }
+}
+
+impl<T: ?Sized> DerefMut for HashedRcn<T> {
+    /// Borrows the value mutably, dropping its cached hash.
+    #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.rcn
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for HashedRcn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash_value());
+    }
+}
+
+impl<T: ?Sized + Hash + PartialEq> PartialEq for HashedRcn<T> {
+    fn eq(&self, other: &HashedRcn<T>) -> bool {
+        if HashedRcn::ptr_eq(self, other) {
+            return true;
+        }
+        if self.is_none() || other.is_none() || self.hash_value() != other.hash_value() {
+            return false;
+        }
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Hash + Eq> Eq for HashedRcn<T> {}
+
+impl<T: Clone + Hash> Clone for HashedRcn<T> {
+    /// Copies the value into a new box, along with its hash.
+    fn clone(&self) -> HashedRcn<T> {
+        if self.is_none() {
+            return HashedRcn::none();
+        }
+        let clone = HashedRcn { rcn: Rcn::new(self.get()) };
+        if let Some(hash) = cached(alias::header(self.rcn.ptr)) {
+            store(alias::header(clone.rcn.ptr), hash);
+        }
+        clone
+    }
+}
+
+impl<T: Hash> From<T> for HashedRcn<T> {
+    fn from(value: T) -> HashedRcn<T> {
+        HashedRcn::new(value)
+    }
+}
+
+impl<T: ?Sized> From<Rcn<T>> for HashedRcn<T> {
+    /// Wraps `rcn` without hashing its value yet. A hash cached for its box is used as is.
+    fn from(rcn: Rcn<T>) -> HashedRcn<T> {
+        HashedRcn { rcn }
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for HashedRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for HashedRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn hash_follows_mutations() {
+        let mut a = HashedRcn::new(vec![1, 2]);
+        let b = a.share();
+        let before = a.hash_value();
+        a.push(3);
+        assert!(!b.is_hash_cached());
+        assert_ne!(b.hash_value(), before);
+        assert_eq!(b.hash_value(), HashedRcn::new(vec![1, 2, 3]).hash_value());
+
+        a.set(&vec![1, 2]);
+        assert!(b.is_hash_cached());
+        assert_eq!(b.hash_value(), before);
+    }
+
+    #[test]
+    fn map_keys() {
+        let key = HashedRcn::new(String::from("key"));
+        let mut map = HashMap::new();
+        map.insert(key.share(), 1);
+        assert_eq!(map.get(&key), Some(&1));
+        assert_eq!(map.get(&key.clone()), Some(&1));
+        assert_eq!(map.get(&HashedRcn::new(String::from("other"))), None);
+        assert!(HashedRcn::<String>::none() == HashedRcn::none());
+        assert!(key != HashedRcn::none());
+    }
+
+    #[test]
+    fn hash_stays_with_the_box() {
+        let key = HashedRcn::new(vec![1, 2]);
+        let hash = key.hash_value();
+        let mut rcn = HashedRcn::into_rcn(key);
+        let other = HashedRcn::from(rcn.share());
+        assert!(other.is_hash_cached());
+        assert_eq!(other.hash_value(), hash);
+
+        rcn.push(3);
+        assert!(!other.is_hash_cached());
+        assert_eq!(other.hash_value(), HashedRcn::new(vec![1, 2, 3]).hash_value());
+
+        let name: HashedRcn<str> = HashedRcn::from(Rcn::from("name"));
+        assert!(!name.is_hash_cached());
+        assert_eq!(name.hash_value(), hash_of("name"));
+        assert!(name.is_hash_cached());
+    }
+}
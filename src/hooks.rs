@@ -0,0 +1,352 @@
+//! Drop hooks, finalizers and expiry callbacks, kept in a per-thread side table keyed by box address.
+//!
+//! Boxes without hooks never touch the table: it is only searched while some box on the thread has one.
+//!
+//! A `Weakn` that registers expiry callbacks is pointed at a listener record of its own, with the second
+//! lowest address bit set; boxes and alias boxes are word-aligned, so the bit is free. The address of the
+//! record tells its callbacks apart from those of other `Weakn`s to the box, so they can be removed when
+//! the `Weakn` drops, while the `Weakn` itself stays one word.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use crate::alias;
+use crate::{RcnBox, LISTENED};
+
+const LISTENING: usize = 2;
+
+#[derive(Default)]
+struct Hooks {
+    /// Takes the value out of the box instead of dropping it in place.
+    on_drop: Option<Box<dyn FnOnce(*mut u8)>>,
+    on_orphan: Vec<Box<dyn FnOnce()>>,
+    on_free: Vec<Box<dyn FnOnce()>>,
+    on_expire: Vec<Expiry>,
+}
+
+/// A callback registered through a `Weakn`, removed when its token is cancelled or that `Weakn` drops.
+struct Expiry {
+    /// Address of the listener record of the `Weakn`.
+    listener: usize,
+    id: u64,
+    callback: Box<dyn FnOnce()>,
+}
+
+/// A registration made by [`Weakn::on_expire`](struct.Weakn.html#method.on_expire).
+///
+/// Dropping the token keeps the callback registered until the `Weakn` drops; use
+/// [`cancel`](#method.cancel) to remove it sooner.
+#[derive(Debug)]
+pub struct ExpiryToken {
+    address: usize,
+    id: u64,
+}
+
+impl ExpiryToken {
+    /// Returns a token for a callback that already ran.
+    pub(crate) fn expired() -> ExpiryToken {
+        ExpiryToken { address: 0, id: 0 }
+    }
+
+    /// Deregisters the callback. Returns `false` if it already ran or was removed.
+    pub fn cancel(self) -> bool {
+        // The callback may own handles, so it drops after the table is released.
+        let removed = remove_expiries(self.address, |expiry| expiry.id == self.id);
+        !removed.is_empty()
+    }
+}
+
+impl fmt::Display for ExpiryToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expiry callback #{}", self.id)
+    }
+}
+
+thread_local! {
+    static NEXT_ID: Cell<u64> = const { Cell::new(1) };
+    static HOOKED: Cell<usize> = const { Cell::new(0) };
+    static TABLE: RefCell<Option<HashMap<usize, Hooks>>> = const { RefCell::new(None) };
+}
+
+/// Returns a new id for expiry callbacks.
+fn next_id() -> u64 {
+    NEXT_ID.with(|next| next.replace(next.get() + 1))
+}
+
+fn address<T: ?Sized>(ptr: *const RcnBox<T>) -> usize {
+    ptr as *const () as usize
+}
+
+fn with_hooks<R>(ptr: usize, f: impl FnOnce(&mut Hooks) -> R) -> R {
+    TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let table = table.get_or_insert_with(HashMap::new);
+        let hooks = table.entry(ptr).or_insert_with(|| {
+            HOOKED.with(|hooked| hooked.set(hooked.get() + 1));
+            Hooks::default()
+        });
+        f(hooks)
+    })
+}
+
+fn take_hooks(ptr: usize) -> Option<Hooks> {
+    if HOOKED.try_with(Cell::get).unwrap_or(0) == 0 {
+        return None;
+    }
+    let hooks = TABLE.try_with(|table| table.borrow_mut().as_mut().and_then(|table| table.remove(&ptr))).ok().flatten();
+    if hooks.is_some() {
+        HOOKED.with(|hooked| hooked.set(hooked.get() - 1));
+    }
+    hooks
+}
+
+/// Installs the hook that receives the value of the box at `ptr` instead of dropping it.
+pub(crate) fn set_drop_hook<T: 'static, F: FnOnce(T) + 'static>(ptr: *const RcnBox<T>, hook: F) {
+    let on_drop: Box<dyn FnOnce(*mut u8)> = Box::new(move |value| hook(unsafe { ptr::read(value as *mut T) }));
+    with_hooks(address(ptr), |hooks| hooks.on_drop = Some(on_drop));
+}
+
+/// Adds a hook that runs when the last strong reference to the box at `ptr` drops while weak ones remain.
+pub(crate) fn add_orphan_hook<T: ?Sized>(ptr: *const RcnBox<T>, hook: Box<dyn FnOnce()>) {
+    with_hooks(address(ptr), |hooks| hooks.on_orphan.push(hook));
+}
+
+/// Adds a hook that runs when the memory of the box at `ptr` is released.
+pub(crate) fn add_free_hook<T: ?Sized>(ptr: *const RcnBox<T>, hook: Box<dyn FnOnce()>) {
+    with_hooks(address(ptr), |hooks| hooks.on_free.push(hook));
+}
+
+/// The record a listening `Weakn` points to.
+struct Listener<T: ?Sized> {
+    target: NonNull<RcnBox<T>>,
+}
+
+/// Returns `true` if the `Weakn` pointer `ptr` points to a listener record.
+#[inline(always)]
+pub(crate) fn is_listening<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> bool {
+    let address = address(ptr.as_ptr());
+    address & LISTENING != 0 && address != usize::MAX
+}
+
+fn listener<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> *mut Listener<T> {
+    ptr::with_exposed_provenance_mut(address(ptr.as_ptr()) & !LISTENING)
+}
+
+/// Returns the box, or alias box, behind the `Weakn` pointer `ptr`.
+#[inline(always)]
+pub(crate) fn target<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> NonNull<RcnBox<T>> {
+    if is_listening(ptr) {
+        unsafe { (*listener(ptr)).target }
+    } else {
+        ptr
+    }
+}
+
+/// Returns the pointer a `Weakn` holding `ptr` switches to before it registers expiry callbacks.
+pub(crate) fn listen<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> NonNull<RcnBox<T>> {
+    if is_listening(ptr) {
+        return ptr;
+    }
+    let address = Box::into_raw(Box::new(Listener { target: ptr })).expose_provenance();
+    unsafe { NonNull::new_unchecked(ptr.as_ptr().with_addr(address | LISTENING)) }
+}
+
+/// Removes the expiry callbacks of a dropping `Weakn` and frees its listener record. Returns the pointer
+/// to the box behind it.
+pub(crate) unsafe fn unlisten<T: ?Sized>(ptr: NonNull<RcnBox<T>>) -> NonNull<RcnBox<T>> {
+    let record = Box::from_raw(listener(ptr));
+    let header = alias::header(record.target);
+    if (*header).has_flag(LISTENED) {
+        let id = listener(ptr) as usize;
+        drop(remove_expiries(address(header), |expiry| expiry.listener == id));
+    }
+    record.target
+}
+
+/// Registers `callback` to run when the strong count of the box behind the listening `Weakn` pointer
+/// `ptr` reaches zero.
+pub(crate) fn add_expiry<T: ?Sized>(ptr: NonNull<RcnBox<T>>, callback: Box<dyn FnOnce()>) -> ExpiryToken {
+    let header = alias::header(target(ptr));
+    let (listener, id) = (listener(ptr) as usize, next_id());
+    with_hooks(address(header), |hooks| hooks.on_expire.push(Expiry { listener, id, callback }));
+    unsafe { (*header).set_flag(LISTENED, true) };
+    ExpiryToken { address: address(header), id }
+}
+
+fn remove_expiries(address: usize, matches: impl Fn(&Expiry) -> bool) -> Vec<Expiry> {
+    TABLE.try_with(|table| {
+        let mut table = table.borrow_mut();
+        match table.as_mut().and_then(|table| table.get_mut(&address)) {
+            Some(hooks) => {
+                let (removed, kept) = mem::take(&mut hooks.on_expire).into_iter().partition(matches);
+                hooks.on_expire = kept;
+                removed
+            }
+            None => Vec::new(),
+        }
+    }).unwrap_or_default()
+}
+
+/// Drops the value of a box whose strong count reached zero, or hands it to its drop hook. The caller
+/// holds one weak reference of its own while this runs.
+pub(crate) unsafe fn drop_value<T: ?Sized>(ptr: *mut RcnBox<T>) {
+    let value = ptr::addr_of_mut!((*ptr).value);
+    if HOOKED.try_with(Cell::get).unwrap_or(0) == 0 {
+        ptr::drop_in_place(value);
+        return;
+    }
+    let (on_drop, on_orphan, on_expire) = TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        match table.as_mut().and_then(|table| table.get_mut(&address(ptr))) {
+            Some(hooks) => (hooks.on_drop.take(), mem::take(&mut hooks.on_orphan), mem::take(&mut hooks.on_expire)),
+            None => (None, Vec::new(), Vec::new()),
+        }
+    });
+    (*ptr).set_flag(LISTENED, false);
+    if (*ptr).weak_count() > 1 {
+        on_orphan.into_iter().for_each(|hook| hook());
+    }
+    match on_drop {
+        Some(hook) => hook(value as *mut u8),
+        None => ptr::drop_in_place(value),
+    }
+    on_expire.into_iter().for_each(|expiry| (expiry.callback)());
+}
+
+/// Runs the orphan hooks and expiry callbacks of a box whose value was moved out while weak references
+/// remain.
+pub(crate) fn value_moved<T: ?Sized>(ptr: *const RcnBox<T>) {
+    if HOOKED.try_with(Cell::get).unwrap_or(0) == 0 {
+        return;
+    }
+    let (on_orphan, on_expire) = TABLE.with(|table| {
+        table.borrow_mut().as_mut().and_then(|table| table.get_mut(&address(ptr)))
+            .map(|hooks| (mem::take(&mut hooks.on_orphan), mem::take(&mut hooks.on_expire)))
+            .unwrap_or_default()
+    });
+    unsafe { (*ptr).set_flag(LISTENED, false) };
+    on_orphan.into_iter().for_each(|hook| hook());
+    on_expire.into_iter().for_each(|expiry| (expiry.callback)());
+}
+
+/// Forgets the hooks of a box whose memory is released, running its free hooks. Expiry callbacks still
+/// registered, whose value was moved out without any `Weakn` left to see it, run first.
+pub(crate) fn freed<T: ?Sized>(ptr: *const RcnBox<T>) {
+    if let Some(hooks) = take_hooks(address(ptr)) {
+        hooks.on_expire.into_iter().for_each(|expiry| (expiry.callback)());
+        hooks.on_free.into_iter().for_each(|hook| hook());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rcn, Weakn};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn hooks_run_in_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let a = {
+            let log = log.clone();
+            Rcn::new_with_hook(String::from("conn"), move |conn| log.borrow_mut().push(format!("returned {}", conn)))
+        };
+        let l = log.clone();
+        a.on_orphan(move || l.borrow_mut().push(String::from("orphaned")));
+        let l = log.clone();
+        a.on_free(move || l.borrow_mut().push(String::from("freed")));
+
+        let w = a.downgrade();
+        drop(a);
+        assert_eq!(*log.borrow(), ["orphaned", "returned conn"]);
+        drop(w);
+        assert_eq!(*log.borrow(), ["orphaned", "returned conn", "freed"]);
+    }
+
+    #[test]
+    fn unsized_and_moved_values() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let s: Rcn<str> = Rcn::from("unsized");
+        let l = log.clone();
+        s.on_free(move || l.borrow_mut().push("str freed"));
+        let l = log.clone();
+        s.on_orphan(move || l.borrow_mut().push("never"));
+        drop(s);
+        assert_eq!(*log.borrow(), ["str freed"]);
+
+        let hooked = Rcn::new_with_hook(5, |_| panic!("the value was moved out"));
+        let l = log.clone();
+        hooked.on_orphan(move || l.borrow_mut().push("unwrapped"));
+        let w = hooked.downgrade();
+        assert_eq!(Rcn::try_unwrap(hooked), Ok(5));
+        assert_eq!(log.borrow().last(), Some(&"unwrapped"));
+        drop(w);
+    }
+
+    #[test]
+    fn expiry_callbacks() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let a = Rcn::new(1);
+        let mut w1 = a.downgrade();
+        let mut w2 = a.downgrade();
+        let l = log.clone();
+        let kept = w1.on_expire(move || l.borrow_mut().push("w1"));
+        let l = log.clone();
+        let cancelled = w1.on_expire(move || l.borrow_mut().push("cancelled"));
+        let l = log.clone();
+        w2.on_expire(move || l.borrow_mut().push("w2"));
+        let resurrect = w1.share();
+        let l = log.clone();
+        w1.on_expire(move || l.borrow_mut().push(if resurrect.upgrade().is_none() { "dead" } else { "alive" }));
+
+        assert!(cancelled.cancel());
+        drop(w2);
+        drop(a);
+        assert_eq!(*log.borrow(), ["w1", "dead"]);
+        assert!(!kept.cancel());
+
+        let l = log.clone();
+        w1.on_expire(move || l.borrow_mut().push("late"));
+        assert_eq!(log.borrow().last(), Some(&"late"));
+        let l = log.clone();
+        Weakn::<i32>::new().on_expire(move || l.borrow_mut().push("none"));
+        assert_eq!(log.borrow().last(), Some(&"none"));
+    }
+
+    #[test]
+    fn expiry_callbacks_belong_to_their_weakn() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let a = Rcn::new(1);
+        let mut w = a.downgrade();
+        let mut copy = w.share();
+        let l = log.clone();
+        w.on_expire(move || l.borrow_mut().push("w"));
+        let l = log.clone();
+        copy.on_expire(move || l.borrow_mut().push("copy"));
+        assert_eq!(*w, 1);
+        assert_eq!(a.weak_count(), 2);
+
+        drop(w);
+        assert_eq!(a.weak_count(), 1);
+        drop(a);
+        assert_eq!(*log.borrow(), ["copy"]);
+        assert!(copy.upgrade().is_none());
+    }
+
+    #[test]
+    fn leftover_expiry_callbacks_run_when_the_box_is_freed() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut moved = Rcn::new(String::from("moved"));
+        // A registration no `Weakn` holds anymore.
+        let listening = super::listen(moved.ptr);
+        let l = log.clone();
+        super::add_expiry(listening, Box::new(move || l.borrow_mut().push("expired")));
+        unsafe { drop(Box::from_raw(super::listener(listening))) };
+
+        assert_eq!(moved.take().as_deref(), Some("moved"));
+        assert_eq!(*log.borrow(), ["expired"]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Hash-consing: equal values share one box.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::{HashMap, RandomState};
+use std::fmt;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+use crate::{Rcn, Weakn};
+
+/// Smallest number of entries that triggers an automatic purge.
+const MIN_PURGE: usize = 8;
+
+/// An interner that shares equal values: interning a value equal to one still alive returns a handle to
+/// the same box, so [`Rcn::ptr_eq`](struct.Rcn.html#method.ptr_eq) tells interned values apart.
+///
+/// Interned values are [frozen](struct.Rcn.html#method.freeze), so `set`, `DerefMut` and the other
+/// mutating methods panic on them. This is needed for correctness: the interner files each box under the
+/// hash of its value, and a value changed in place would no longer be found, or would be shared with
+/// handles that expect a different value.
+///
+/// Entries are held weakly, so a value is freed when its last `Rcn` drops. Expired entries are purged
+/// automatically once the interner has doubled since the last purge, or with [`purge`](#method.purge).
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, RcnInterner};
+///
+/// let mut names: RcnInterner<str> = RcnInterner::new();
+/// let a = names.intern_ref("alice");
+/// let b = names.intern_ref(&String::from("alice"));
+/// assert!(Rcn::ptr_eq(&a, &b));
+///
+/// drop((a, b));
+/// assert!(names.get("alice").is_none());
+/// assert_eq!(names.stats().hits, 1);
+/// ```
+pub struct RcnInterner<T: ?Sized> {
+    hasher: RandomState,
+    /// Entries by the hash of their value.
+    entries: HashMap<u64, Weakn<T>, BuildHasherDefault<PreHashed>>,
+    /// Further entries whose hash is taken in `entries`.
+    collisions: HashMap<u64, Vec<Weakn<T>>, BuildHasherDefault<PreHashed>>,
+    purge_at: usize,
+    hits: usize,
+    misses: usize,
+}
+
+/// Statistics of an [`RcnInterner`](struct.RcnInterner.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InternStats {
+    /// Lookups that found an equal value alive.
+    pub hits: usize,
+    /// Lookups that had to create a new box.
+    pub misses: usize,
+    /// Entries held, including expired ones not purged yet.
+    pub entries: usize,
+    /// Entries whose value is still alive.
+    pub live: usize,
+}
+
+/// A hasher for keys that already are hashes.
+#[derive(Default)]
+struct PreHashed(u64);
+
+impl Hasher for PreHashed {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+impl<T: ?Sized + Hash + Eq> RcnInterner<T> {
+    /// Constructs an empty interner.
+    pub fn new() -> RcnInterner<T> {
+        RcnInterner {
+            hasher: RandomState::new(),
+            entries: HashMap::default(),
+            collisions: HashMap::default(),
+            purge_at: MIN_PURGE,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the box holding a value equal to `value`, creating it from `value` if there is none.
+    pub fn intern(&mut self, value: T) -> Rcn<T> where T: Sized {
+        let hash = self.hasher.hash_one(&value);
+        match self.lookup(hash, &value) {
+            Some(found) => found,
+            None => self.insert(hash, Rcn::new(value)),
+        }
+    }
+
+    /// Returns the box holding a value equal to `value`, creating it from a copy of `value` if there is
+    /// none. This also interns unsized values such as `str` and `[T]`.
+    pub fn intern_ref<Q>(&mut self, value: &Q) -> Rcn<T>
+    where T: Borrow<Q>, Q: ?Sized + Hash + Eq + ToOwned, Rcn<T>: From<Q::Owned> {
+        let hash = self.hasher.hash_one(value);
+        match self.lookup(hash, value) {
+            Some(found) => found,
+            None => self.insert(hash, Rcn::from(value.to_owned())),
+        }
+    }
+
+    /// Returns the box holding a value equal to `value`, if one is alive. Does not count towards the
+    /// statistics.
+    pub fn get<Q: ?Sized + Hash + Eq>(&self, value: &Q) -> Option<Rcn<T>> where T: Borrow<Q> {
+        self.find(self.hasher.hash_one(value), value)
+    }
+
+    fn find<Q: ?Sized + Eq>(&self, hash: u64, value: &Q) -> Option<Rcn<T>> where T: Borrow<Q> {
+        let matches = |weak: &Weakn<T>| weak.upgrade().filter(|rcn| (**rcn).borrow() == value);
+        if let Some(found) = self.entries.get(&hash).and_then(matches) {
+            return Some(found);
+        }
+        self.collisions.get(&hash)?.iter().find_map(matches)
+    }
+
+    /// Finds `value` and counts the lookup.
+    fn lookup<Q: ?Sized + Eq>(&mut self, hash: u64, value: &Q) -> Option<Rcn<T>> where T: Borrow<Q> {
+        let found = self.find(hash, value);
+        match found {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        found
+    }
+
+    fn insert(&mut self, hash: u64, rcn: Rcn<T>) -> Rcn<T> {
+        if self.len() >= self.purge_at {
+            self.purge();
+        }
+        rcn.freeze();
+        let weak = rcn.downgrade();
+        match self.entries.get_mut(&hash) {
+            Some(slot) if slot.is_none() => *slot = weak,
+            Some(_) => self.collisions.entry(hash).or_default().push(weak),
+            None => drop(self.entries.insert(hash, weak)),
+        }
+        rcn
+    }
+
+    /// Removes the expired entries and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let before = self.len();
+        self.entries.retain(|_, weak| weak.is_some());
+        self.collisions.retain(|_, weaks| {
+            weaks.retain(Weakn::is_some);
+            !weaks.is_empty()
+        });
+        self.purge_at = (self.len() * 2).max(MIN_PURGE);
+        before - self.len()
+    }
+
+    /// Returns the number of entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.collisions.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Returns `true` if the interner has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of entries whose value is still alive.
+    pub fn len_live(&self) -> usize {
+        let collisions = self.collisions.values().flatten();
+        self.entries.values().chain(collisions).filter(|weak| weak.is_some()).count()
+    }
+
+    /// Returns the statistics of this interner. Counting the live entries visits every entry.
+    pub fn stats(&self) -> InternStats {
+        InternStats { hits: self.hits, misses: self.misses, entries: self.len(), live: self.len_live() }
+    }
+
+    /// Removes every entry. Boxes already handed out are not affected.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.collisions.clear();
+        self.purge_at = MIN_PURGE;
+    }
+}
+
+impl<T: ?Sized + Hash + Eq> Default for RcnInterner<T> {
+    fn default() -> RcnInterner<T> {
+        RcnInterner::new()
+    }
+}
+
+impl<T: ?Sized + Hash + Eq> fmt::Debug for RcnInterner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcnInterner").field("stats", &self.stats()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn equal_values_share_a_box() {
+        let mut interner = RcnInterner::new();
+        let a = interner.intern(String::from("a"));
+        let b = interner.intern_ref("a");
+        let c = interner.intern(String::from("c"));
+        assert!(Rcn::ptr_eq(&a, &b));
+        assert!(!Rcn::ptr_eq(&a, &c));
+        assert!(Rcn::ptr_eq(&interner.get("c").unwrap(), &c));
+        assert!(a.is_frozen());
+
+        drop(c);
+        assert_eq!(interner.stats(), InternStats { hits: 1, misses: 2, entries: 2, live: 1 });
+        assert_eq!(interner.purge(), 1);
+        let c = interner.intern_ref("c");
+        assert_eq!(*c, "c");
+        assert_eq!(interner.stats().misses, 3);
+    }
+
+    #[test]
+    fn unsized_values() {
+        let mut strings: RcnInterner<str> = RcnInterner::new();
+        let a = strings.intern_ref("hello");
+        assert!(Rcn::ptr_eq(&a, &strings.intern_ref("hello")));
+
+        let mut slices: RcnInterner<[u8]> = RcnInterner::new();
+        let b = slices.intern_ref(&[1, 2, 3][..]);
+        assert!(Rcn::ptr_eq(&b, &slices.intern_ref(&vec![1, 2, 3][..])));
+        assert!(slices.get(&[1, 2][..]).is_none());
+    }
+
+    #[test]
+    fn colliding_hashes() {
+        let mut interner: RcnInterner<str> = RcnInterner::new();
+        let a = interner.intern_ref("a");
+        let b: Rcn<str> = Rcn::from("b");
+        interner.collisions.entry(interner.hasher.hash_one("a")).or_default().push(b.downgrade());
+        assert!(Rcn::ptr_eq(&interner.intern_ref("a"), &a));
+        assert_eq!(interner.len_live(), 2);
+        drop(b);
+        assert_eq!(interner.purge(), 1);
+        assert!(interner.collisions.is_empty());
+    }
+
+    #[test]
+    fn inserts_purge_amortized() {
+        let mut interner = RcnInterner::new();
+        for i in 0..1000 {
+            interner.intern(i);
+        }
+        assert!(interner.len() <= 16);
+    }
+}
@@ -31,7 +31,7 @@ use std::marker::PhantomData;
 use std::ptr::{self, NonNull};
 use std::cell::Cell;
 #[allow(unused_imports)]
-use std::alloc::{GlobalAlloc, Layout, System, handle_alloc_error};
+use std::alloc::{alloc, dealloc, Layout, handle_alloc_error};
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::cmp::Ordering;
@@ -40,12 +40,216 @@ use std::mem::{self, forget};
 use std::rc::Rc;
 // use std::any::Any;
 
+#[cfg(any(feature = "leak-check", feature = "track-holders"))]
+pub mod debug;
+#[cfg(feature = "checked")]
+mod checked;
+mod count;
+pub mod compact;
+pub mod collections;
+mod thin;
+mod zst;
+mod region;
+mod pool;
+mod scope;
+mod batch;
+mod drops;
+mod hooks;
+mod alias;
+mod borrow;
+mod unique;
+mod frozen;
+mod reader;
+mod poison;
+mod transaction;
+mod interner;
+mod hashed;
+mod projection;
+
+pub use count::{OverflowPolicy, OverflowError, set_overflow_policy, overflow_policy};
+pub use thin::{ThinRcn, ThinWeakn, ThinDst};
+pub use pool::{RcnPool, PoolStats};
+pub use scope::RcnScope;
+pub use drops::{defer_drops, flush_deferred};
+pub use hooks::ExpiryToken;
+pub use interner::{RcnInterner, InternStats};
+pub use hashed::HashedRcn;
+pub use projection::{RcnRef, WeaknRef};
+pub use borrow::RcnBorrow;
+pub use unique::UniqueRcn;
+pub use frozen::FrozenRcn;
+pub use reader::{RcnReader, WeakReader};
+pub use poison::PoisonError;
+pub use transaction::{transaction, Snapshot};
+
+/// Number of flag bits kept at the top of the weak count word.
+const FLAG_BITS: u32 = 6;
+/// The largest weak count, which marks a saturated count. The bits above it hold the flags.
+const WEAK_MAX: usize = usize::MAX >> FLAG_BITS;
+/// The memory of the box belongs to a region, such as a pool, that takes it back.
+const OWNED: usize = 1 << (usize::BITS - 1);
+/// The value can no longer be changed, see [`Rcn::freeze`](struct.Rcn.html#method.freeze).
+const FROZEN: usize = 1 << (usize::BITS - 2);
+/// Some [`RcnReader`](struct.RcnReader.html)s to the box are counted in a side table. They hold strong
+/// references, so the count is gone before the box is freed.
+const HAS_READERS: usize = 1 << (usize::BITS - 3);
+/// A panic interrupted a [`modify`](struct.Rcn.html#method.modify) of the value.
+const POISONED: usize = 1 << (usize::BITS - 4);
+/// Some `Weakn` registered expiry callbacks for the box in the hooks table.
+const LISTENED: usize = 1 << (usize::BITS - 5);
+/// The hash of the value is cached in the side table of [`HashedRcn`](struct.HashedRcn.html). Any write
+/// to the value drops it.
+const HASHED: usize = 1 << (usize::BITS - 6);
+
+#[repr(C)]
 struct RcnBox<T: ?Sized> {
     strong: Cell<usize>,
     weak: Cell<usize>,
+    #[cfg(feature = "checked")]
+    canary: Cell<usize>,
+    #[cfg(feature = "checked")]
+    generation: Cell<u64>,
     value: T,
 }
 
+impl<T> RcnBox<T> {
+    /// Returns a box holding `value` with one strong and no weak references.
+    fn with_value(value: T) -> RcnBox<T> {
+        RcnBox {
+            strong: Cell::new(1),
+            weak: Cell::new(0),
+            #[cfg(feature = "checked")]
+            canary: Cell::new(checked::LIVE),
+            #[cfg(feature = "checked")]
+            generation: Cell::new(checked::next_generation()),
+            value,
+        }
+    }
+
+    /// Returns a box holding `value` for memory owned by a region, which takes it back on release.
+    fn owned(value: T) -> RcnBox<T> {
+        let owned = RcnBox::with_value(value);
+        owned.set_flag(OWNED, true);
+        owned
+    }
+
+    /// Allocates a new box holding `value` with one strong and no weak references.
+    fn allocate(value: T) -> NonNull<RcnBox<T>> {
+        let ptr = Box::into_raw(Box::new(RcnBox::with_value(value)));
+        unsafe {
+            RcnBox::register(ptr);
+            NonNull::new_unchecked(ptr)
+        }
+    }
+
+    /// Returns the sentinel of none handles. It is never dereferenced, and no allocation can live at
+    /// `usize::MAX`, so it cannot be mistaken for a box.
+    fn dangling() -> NonNull<RcnBox<T>> {
+        unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(usize::MAX)) }
+    }
+}
+
+impl<T> RcnBox<[T]> {
+    /// Allocates a box for `len` elements with one strong and no weak references. The elements are left
+    /// uninitialized and the box must be passed to `register` once they are written.
+    unsafe fn allocate_slice(len: usize) -> *mut RcnBox<[T]> {
+        let layout = Layout::array::<T>(len)
+            .and_then(|array| Layout::new::<RcnBox<()>>().extend(array))
+            .expect("Rcn slice too large")
+            .0
+            .pad_to_align();
+        let mem = alloc(layout);
+        if mem.is_null() {
+            handle_alloc_error(layout);
+        }
+        ptr::write(mem as *mut RcnBox<()>, RcnBox::with_value(()));
+        ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut RcnBox<[T]>
+    }
+
+    /// Allocates a box holding clones of the items of `v`, to be passed to `register`.
+    unsafe fn from_slice(v: &[T]) -> *mut RcnBox<[T]> where T: Clone {
+        let ptr = RcnBox::<[T]>::allocate_slice(v.len());
+        let data = ptr::addr_of_mut!((*ptr).value) as *mut T;
+        for (i, item) in v.iter().enumerate() {
+            ptr::write(data.add(i), item.clone());
+        }
+        ptr
+    }
+}
+
+impl<T: ?Sized> RcnBox<T> {
+    /// Returns `true` if `ptr` is the sentinel of a none handle.
+    #[inline]
+    fn is_dangling(ptr: NonNull<RcnBox<T>>) -> bool {
+        ptr.as_ptr() as *mut () as usize == usize::MAX
+    }
+
+    /// Returns the weak count, without the flags sharing its word.
+    #[inline(always)]
+    fn weak_count(&self) -> usize {
+        self.weak.get() & WEAK_MAX
+    }
+
+    /// Sets the weak count, keeping the flags.
+    #[inline(always)]
+    fn set_weak_count(&self, weak: usize) {
+        self.weak.set(self.weak.get() & !WEAK_MAX | weak);
+    }
+
+    #[inline(always)]
+    fn has_flag(&self, flag: usize) -> bool {
+        self.weak.get() & flag != 0
+    }
+
+    #[inline(always)]
+    fn set_flag(&self, flag: usize, on: bool) {
+        if on {
+            self.weak.set(self.weak.get() | flag);
+        } else {
+            self.weak.set(self.weak.get() & !flag);
+        }
+    }
+
+    /// Records a new, fully initialized allocation with the debugging features.
+    #[allow(unused_variables)]
+    unsafe fn register(ptr: *mut RcnBox<T>) {
+        #[cfg(feature = "leak-check")]
+        debug::register(ptr, mem::size_of_val(&*ptr));
+    }
+
+    /// Releases the memory of a box whose value was already dropped or moved out.
+    unsafe fn deallocate(ptr: *mut RcnBox<T>) {
+        hooks::freed(ptr);
+        #[cfg(feature = "leak-check")]
+        debug::unregister(ptr);
+        #[cfg(feature = "track-holders")]
+        debug::forget_box(ptr as *const () as usize);
+        if (*ptr).has_flag(HASHED) {
+            hashed::forget(ptr as *mut () as *mut RcnBox<()>);
+        }
+        if mem::size_of_val(&(*ptr).value) == 0 && zst::retain(ptr as *mut () as *mut RcnBox<()>) {
+            return;
+        }
+        let layout = Layout::for_value(&*ptr);
+        #[cfg(feature = "checked")]
+        (*ptr).canary.set(checked::FREED);
+        if (*ptr).has_flag(OWNED) && region::release(ptr as *mut u8, layout) {
+            return;
+        }
+        drops::free(ptr as *mut u8, layout);
+    }
+
+    /// Returns the generation tag a new handle to `ptr` has to remember.
+    #[cfg(feature = "checked")]
+    fn generation_of(ptr: NonNull<RcnBox<T>>) -> u64 {
+        if RcnBox::is_dangling(ptr) {
+            0
+        } else {
+            unsafe { (*alias::header(ptr)).generation.get() }
+        }
+    }
+}
+
 
 // impl<T> RcnBox<T>{
 //     pub fn new<'a>(mut self, data: T) -> &'a mut Self where T: 'a
@@ -63,14 +267,22 @@ struct RcnBox<T: ?Sized> {
 // }
 
 /// A single-threaded reference-counting pointer with none value. `Rcn` stands for 'Reference Counted with None values'.
-/// 
-/// 
-/// 
-/// 
-
+///
+/// For sized `T`, and without the `track-holders` and `checked` features, an `Rcn<T>` is exactly one
+/// pointer wide, and so are an `Option<Rcn<T>>` and a `Weakn<T>`.
+///
+/// A value is dropped as soon as its last strong handle goes, unless that happens while another value is
+/// being dropped on the same thread. The value is then queued and dropped after the outermost destructor
+/// returns, in the order the handles went, so dropping long chains such as linked lists does not overflow
+/// the stack. A `Drop` impl therefore cannot rely on the values behind its `Rcn` fields, or behind handles
+/// it releases itself, being dropped before it returns.
 pub struct Rcn<T: ?Sized>{
-    ptr: *mut RcnBox<T>,
+    ptr: NonNull<RcnBox<T>>,
     phantom: PhantomData<T>,
+    #[cfg(feature = "track-holders")]
+    holder: u64,
+    #[cfg(feature = "checked")]
+    generation: u64,
 }
 
 #[allow(dead_code)]
@@ -86,20 +298,32 @@ impl<T> Rcn<T> {
     /// let ten = Rcn::new(10);
     /// assert_eq!(ten.is_some(), true);
     /// ```
+    ///
+    /// Zero-sized values can share one header per thread instead, see [`new_zst`](#method.new_zst).
+    #[track_caller]
     pub fn new(data: T) -> Rcn<T>{
-        Rcn::<T> {
-            ptr: Box::into_raw(Box::new(RcnBox::<T> {
-                        strong: Cell::new(1),
-                        weak: Cell::new(0),
-                        value: data,
-                    })),
-            // ptr: Box::leak(Box::new(RcnBox::<T> {
-            //         strong: Cell::new(1),
-            //         weak: Cell::new(0),
-            //         value: data,
-            //     })),
-            phantom: PhantomData,
-        }
+        Rcn::from_inner(RcnBox::allocate(data))
+    }
+
+    /// Constructs one `Rcn<T>` per item of `iter`, placing all of their boxes in a single allocation.
+    /// Every member keeps its own strong and weak counts, and the allocation is freed once the last
+    /// reference to any member, strong or weak, is gone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let mut records = Rcn::new_batch(vec!["a", "b", "c"]);
+    /// let b = records[1].share();
+    /// assert_eq!(records[0].take(), Some("a"));
+    /// drop(records);
+    /// assert_eq!(*b, "b");
+    /// ```
+    #[track_caller]
+    pub fn new_batch<I: IntoIterator<Item = T>>(iter: I) -> Vec<Rcn<T>> {
+        batch::allocate(iter.into_iter().collect())
     }
 
     /// Constructs a `Rcn<T>` with none value. 
@@ -114,10 +338,7 @@ impl<T> Rcn<T> {
     /// assert_eq!(ten.is_none(), true);
     /// ```
     pub fn none() -> Rcn<T> {
-        Rcn::<T> {
-            ptr: ptr::null_mut(),
-            phantom: PhantomData,
-        }
+        Rcn::from_inner(RcnBox::dangling())
     }
 
     /// Constructs a `Rcn<T>` with none value. 
@@ -142,16 +363,47 @@ impl<T> Rcn<T> {
     #[inline]
     pub fn take(&mut self) -> Option<T> {
         unsafe {
-            if self.is_unique() {
-                let out_ptr = self.ptr;
-                self.ptr = 0 as *mut RcnBox<T>;
-                Some(out_ptr.read().value)
+            if self.is_unique() && !alias::is_alias(self.ptr) && !self.frozen() {
+                self.untrack();
+                let out_ptr = mem::replace(&mut self.ptr, RcnBox::dangling()).as_ptr();
+                let value = ptr::read(&(*out_ptr).value);
+                RcnBox::deallocate(out_ptr);
+                Some(value)
             } else {
                 None
             }
         }
     }
 
+    /// Replaces the value with `value` and returns the old one, which is visible to every holder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none or [frozen](#method.freeze).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let mut a = Rcn::new(1);
+    /// let b = a.share();
+    /// assert_eq!(a.replace(2), 1);
+    /// assert_eq!(*b, 2);
+    /// ```
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn replace(&mut self, value: T) -> T {
+        if self.is_none() {
+            panic!("write (replace) in none rcn!");
+        }
+        if self.frozen() {
+            panic!("write (replace) in frozen rcn!");
+        }
+        self.unhash();
+        unsafe { mem::replace(&mut *self.value(), value) }
+    }
+
     
     /// Returns the contained value, if the `Rcn` has exactly one strong reference.
     ///
@@ -176,16 +428,20 @@ impl<T> Rcn<T> {
     /// assert_eq!(*Rcn::try_unwrap(x).unwrap_err(), 4);
     /// ```
     #[inline]
-    pub fn try_unwrap(this: Self) -> Result<T, Self> {
-        if this.strong_count() == 1 {
+    pub fn try_unwrap(mut this: Self) -> Result<T, Self> {
+        if this.strong_count() == 1 && !alias::is_alias(this.ptr) && !this.frozen() {
             unsafe {
                 let val = ptr::read(&*this); // copy the contained object
 
                 this.dec_strong();
+                if this.weak() > 0 {
+                    hooks::value_moved(this.ptr.as_ptr());
+                }
 
                 this.inc_weak();
-                let _weak = Weakn { ptr: this.ptr };
+                let _weak = Weakn::from_inner(this.ptr);
                 
+                this.untrack();
                 forget(this);
                 Ok(val)
             }
@@ -195,6 +451,68 @@ impl<T> Rcn<T> {
     }
 }
 
+impl<T: 'static> Rcn<T> {
+    /// Constructs a new `Rcn<T>` for a zero-sized `T`, sharing one header with every other `Rcn<T>`
+    /// created this way on the current thread. Only the counts are stored, so no allocation happens
+    /// while the type already has a header, and the header is cached for reuse when its last handle
+    /// goes away.
+    ///
+    /// The header holds a single value: the first one passed in is dropped when the last handle goes
+    /// away, and values passed while the header is in use are dropped right away.
+    ///
+    /// Sharing is opt-in: [`new`](#method.new) gives even zero-sized values a box of their own. The state
+    /// attached to a shared header is shared too, so [freezing](#method.freeze), poisoning and hooks set
+    /// through one handle affect every `Rcn<T>` made by `new_zst` until the header goes idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not zero-sized.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// struct CanWrite;
+    ///
+    /// let first = Rcn::new_zst(CanWrite);
+    /// let second = Rcn::new_zst(CanWrite);
+    /// assert!(Rcn::ptr_eq(&first, &second));
+    /// assert_eq!(first.strong_count(), 2);
+    /// ```
+    #[track_caller]
+    pub fn new_zst(value: T) -> Rcn<T> {
+        assert!(mem::size_of::<T>() == 0, "Rcn::new_zst of a type that is not zero-sized");
+        Rcn::from_inner(zst::header(value))
+    }
+
+    /// Constructs a new `Rcn<T>` whose value is passed to `hook` instead of being dropped when the last
+    /// strong reference goes away. Values moved out by [`take`](#method.take) or
+    /// [`try_unwrap`](#method.try_unwrap) skip the hook.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let idle = Rc::new(RefCell::new(Vec::new()));
+    /// let returned = idle.clone();
+    /// let conn = Rcn::new_with_hook(String::from("db:5432"), move |conn| returned.borrow_mut().push(conn));
+    /// drop(conn);
+    /// assert_eq!(*idle.borrow(), ["db:5432"]);
+    /// ```
+    #[track_caller]
+    pub fn new_with_hook<F: FnOnce(T) + 'static>(value: T, hook: F) -> Rcn<T> {
+        let rcn = Rcn::new(value);
+        hooks::set_drop_hook(rcn.ptr.as_ptr(), hook);
+        rcn
+    }
+}
+
 #[allow(dead_code)]
 impl<T: ?Sized> Rcn<T> {
 
@@ -213,6 +531,7 @@ impl<T: ?Sized> Rcn<T> {
     /// assert_eq!(2, ten.strong_count());
     /// ```
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn strong_count(&self) -> usize {
         self.strong()
     }
@@ -232,6 +551,7 @@ impl<T: ?Sized> Rcn<T> {
     /// assert_eq!(1, ten.strong_count());
     /// ```
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn weak_count(&self) -> usize {
         self.weak()
     }
@@ -254,6 +574,7 @@ impl<T: ?Sized> Rcn<T> {
     /// assert!(!x.is_unique()); // weak_count == 1 and strong_count == 1
     /// ```
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn is_unique(&self) -> bool {
         self.weak_count() == 0 && self.strong_count() == 1
     }
@@ -277,8 +598,9 @@ impl<T: ?Sized> Rcn<T> {
     /// assert!(!n4.is_none());               // Value is 10
     /// ```
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn is_none(&self) -> bool {
-        self.strong() == 0  || self.ptr.is_null()
+        self.strong() == 0  || self.is_dangling()
     }
 
     /// Returns `true` if the current `Rcn` pointer is not `None`.
@@ -299,8 +621,9 @@ impl<T: ?Sized> Rcn<T> {
     /// n4 = Rcn::new(10);                    // OK
     /// assert!(n4.is_some());               // Value is 10
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn is_some(&self) -> bool {
-        self.strong() > 0 && !self.ptr.is_null()
+        self.strong() > 0 && !self.is_dangling()
     }
 
     /// Returns true if the two `Rcn`s point to the same value (not
@@ -323,7 +646,11 @@ impl<T: ?Sized> Rcn<T> {
     /// ```
     #[inline]
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        this.ptr == other.ptr
+        if alias::is_alias(this.ptr) || alias::is_alias(other.ptr) {
+            // Aliases made separately from the same value have their own alias boxes.
+            return this.is_some() && other.is_some() && ptr::addr_eq(this.value(), other.value());
+        }
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
     }
 
     /// This creates another pointer to the same inner value, increasing the strong reference count.
@@ -350,19 +677,58 @@ impl<T: ?Sized> Rcn<T> {
     /// assert_eq!(90, shared_ptr.get());
     /// ```
     #[inline]
+    #[track_caller]
     pub fn share(&self) -> Rcn<T> {
         if self.is_some() {
             self.inc_strong();
-            Rcn {
-                ptr: self.ptr,
-                phantom: PhantomData,
-            }
+            Rcn::from_inner(self.ptr)
         } else {
             panic!("share of Rcn with none value");
         }
     }
 
 
+    /// Creates a read-only handle to this value. The [`RcnReader`] sees every change made through the
+    /// `Rcn`s to the value, but cannot make any itself, and cannot be turned back into an `Rcn`.
+    ///
+    /// [`RcnReader`]: struct.RcnReader.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let mut status = Rcn::new(String::from("starting"));
+    /// let view = status.share_readonly();
+    /// status.push_str(" (2/3)");
+    /// assert_eq!(*view, "starting (2/3)");
+    /// assert_eq!(status.reader_count(), 1);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn share_readonly(&self) -> RcnReader<T> {
+        if self.is_none() {
+            panic!("share_readonly of Rcn with none value");
+        }
+        RcnReader::new(self.share())
+    }
+
+    /// Gets the number of [`RcnReader`](struct.RcnReader.html)s to the value. They are included in the
+    /// [`strong_count`](#method.strong_count).
+    #[inline]
+    pub fn reader_count(&self) -> usize {
+        if self.is_none() {
+            0
+        } else {
+            reader::readers(alias::header(self.ptr))
+        }
+    }
+
     /// Creates a new [`Weakn`][weakn] pointer to this value. NOTE: This function don't destroy current Rcn pointer. 
     ///
     /// [weakn]: struct.Weakn.html
@@ -377,11 +743,52 @@ impl<T: ?Sized> Rcn<T> {
     ///
     /// let weak_five = Rcn::downgrade(&five); //strong_count = 1 and weak_count = 1
     /// ```
+    #[track_caller]
     pub fn downgrade(&self) -> Weakn<T> {
-        self.inc_weak();
-        let address = self.ptr as *mut () as usize;
-        debug_assert!(address != usize::max_value());
-        Weakn { ptr: self.ptr }
+        if !self.is_dangling() {
+            self.inc_weak();
+        }
+        Weakn::from_inner(self.ptr)
+    }
+
+    /// Like [`share`](#method.share), but returns an [`OverflowError`] instead of applying the
+    /// [`OverflowPolicy`] when the strong count is at its limit.
+    ///
+    /// [`OverflowError`]: struct.OverflowError.html
+    /// [`OverflowPolicy`]: enum.OverflowPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let ptr = Rcn::new(80);
+    /// let shared_ptr = ptr.try_share().expect("strong count overflow");
+    /// assert_eq!(2, shared_ptr.strong_count());
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn try_share(&self) -> Result<Rcn<T>, OverflowError> {
+        if self.is_some() {
+            self.try_inc_strong()?;
+            Ok(Rcn::from_inner(self.ptr))
+        } else {
+            panic!("share of Rcn with none value");
+        }
+    }
+
+    /// Like [`downgrade`](#method.downgrade), but returns an [`OverflowError`] instead of applying the
+    /// [`OverflowPolicy`] when the weak count is at its limit.
+    ///
+    /// [`OverflowError`]: struct.OverflowError.html
+    /// [`OverflowPolicy`]: enum.OverflowPolicy.html
+    #[track_caller]
+    pub fn try_downgrade(&self) -> Result<Weakn<T>, OverflowError> {
+        if !self.is_dangling() {
+            self.try_inc_weak()?;
+        }
+        Ok(Weakn::from_inner(self.ptr))
     }
 
     /// Consumes the `Rcn`, returning the wrapped pointer.
@@ -408,76 +815,510 @@ impl<T: ?Sized> Rcn<T> {
         ptr
     }
 
+    /// Constructs a new `Rcn<T>` holding a clone of the value behind `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null and point to a valid, initialized `T`.
+    #[track_caller]
     pub unsafe fn from_raw(ptr: *const T) -> Rcn<T> where T: Clone{
+        #[cfg(feature = "checked")]
+        checked::verify_raw("Rcn::from_raw", ptr as *const u8);
         let v = ptr.as_ref().unwrap();
 
-        let rcn = Rcn::<T> {
-            ptr: Box::into_raw(Box::new(RcnBox::<T> {
-                    strong: Cell::new(1),
-                    weak: Cell::new(0),
-                    value: (*v).clone(),
-                })),
+        Rcn::from_inner(RcnBox::allocate((*v).clone()))
+    }
+
+    /// Makes the value immutable for every holder: from now on [`set`](#method.set), `DerefMut` and
+    /// [`replace`](#method.replace) panic, and [`take`](#method.take), [`try_unwrap`](#method.try_unwrap)
+    /// and [`try_into_unique`](#method.try_into_unique) fail. Freezing cannot be undone. Freezing an
+    /// aliasing handle freezes the box it aliases.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let config = Rcn::new(vec![String::from("verbose")]);
+    /// let mut plugin = config.share();
+    /// config.freeze();
+    /// assert!(plugin.is_frozen());
+    ///
+    /// let attempt = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| plugin.push(String::from("quiet"))));
+    /// assert!(attempt.is_err());
+    /// assert_eq!(config.len(), 1);
+    /// ```
+    #[track_caller]
+    pub fn freeze(&self) {
+        if self.is_none() {
+            panic!("freeze of Rcn with none value");
+        }
+        unsafe { (*alias::header(self.ptr)).set_flag(FROZEN, true) };
+    }
+
+    /// Returns `true` if the value was [frozen](#method.freeze).
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        !self.is_dangling() && self.frozen()
+    }
+
+    /// Freezes the value and returns a [`FrozenRcn`] handle to it, which has no mutating methods.
+    ///
+    /// [`FrozenRcn`]: struct.FrozenRcn.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    #[track_caller]
+    pub fn into_frozen(this: Self) -> FrozenRcn<T> {
+        this.freeze();
+        FrozenRcn::new(this)
+    }
+
+    /// Runs `f` on the value and returns its result. If `f` panics, the value is marked as poisoned for
+    /// every holder, like a `Mutex` is, since it may be left half-modified. The `try_` accessors then
+    /// return a [`PoisonError`] until [`clear_poison`](#method.clear_poison) is called.
+    ///
+    /// [`PoisonError`]: struct.PoisonError.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none or [frozen](#method.freeze).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// let mut balance = Rcn::new((100, 0));
+    /// let audit = balance.share();
+    /// let transfer = panic::catch_unwind(AssertUnwindSafe(|| balance.modify(|(from, to)| {
+    ///     *from -= 30;
+    ///     if *from < 80 {
+    ///         panic!("limit exceeded");
+    ///     }
+    ///     *to += 30;
+    /// })));
+    /// assert!(transfer.is_err());
+    /// assert!(audit.is_poisoned());
+    /// ```
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn modify<R, F: FnOnce(&mut T) -> R>(&mut self, f: F) -> R {
+        let header = alias::header(self.ptr);
+        // Deref first, so a panic on a none or frozen `Rcn` does not poison it.
+        let value: &mut T = self;
+        let _modifying = poison::Modifying::new(header);
+        f(value)
+    }
+
+    /// Like [`modify`](#method.modify), but returns an error carrying the value instead of running `f` if
+    /// the value is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none or frozen.
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn try_modify<R, F: FnOnce(&mut T) -> R>(&mut self, f: F) -> Result<R, PoisonError<&mut T>> {
+        if self.is_poisoned() {
+            Err(PoisonError::new(&mut **self))
+        } else {
+            Ok(self.modify(f))
+        }
+    }
+
+    /// Returns a reference to the value, or an error carrying it if the value is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn try_read(&self) -> Result<&T, PoisonError<&T>> {
+        if self.is_poisoned() {
+            Err(PoisonError::new(&**self))
+        } else {
+            Ok(&**self)
+        }
+    }
+
+    /// Returns a mutable reference to the value, or an error carrying it if the value is poisoned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none or frozen.
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn try_write(&mut self) -> Result<&mut T, PoisonError<&mut T>> {
+        if self.is_poisoned() {
+            Err(PoisonError::new(&mut **self))
+        } else {
+            Ok(&mut **self)
+        }
+    }
+
+    /// Runs `f` on the value and keeps its changes only if it returns `Ok`. If it returns `Err` or panics,
+    /// the value is restored from a [`Snapshot`] taken beforehand, so the other holders never see a
+    /// partial update. See [`transaction`] for nesting transactions.
+    ///
+    /// The snapshot is taken even when this is the only handle to the value: there is no one else to hide a
+    /// partial update from, but `f` may still fail and the value must then be put back, which takes the
+    /// saved state all the same. A failed update is therefore always undone and never
+    /// [poisons](#method.modify) the value. Implement [`Snapshot`] by hand to make it cheaper.
+    ///
+    /// [`Snapshot`]: trait.Snapshot.html
+    /// [`transaction`]: fn.transaction.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none or [frozen](#method.freeze).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let mut range = Rcn::new((0, 10));
+    /// let view = range.share();
+    /// let moved: Result<(), &str> = range.transaction(|(start, end)| {
+    ///     *start += 15;
+    ///     if *start > *end {
+    ///         return Err("empty range");
+    ///     }
+    ///     *end += 15;
+    ///     Ok(())
+    /// });
+    /// assert_eq!(moved, Err("empty range"));
+    /// assert_eq!(*view, (0, 10));
+    /// ```
+    #[cfg_attr(feature = "checked", track_caller)]
+    pub fn transaction<R, E, F>(&mut self, f: F) -> Result<R, E>
+    where
+        T: Snapshot,
+        F: FnOnce(&mut T) -> Result<R, E>,
+    {
+        transaction(&mut **self, f)
+    }
+
+    /// Returns `true` if a panic interrupted a [`modify`](#method.modify) of the value.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        !self.is_dangling() && unsafe { (*alias::header(self.ptr)).has_flag(POISONED) }
+    }
+
+    /// Clears the poisoned flag of the value, for every holder.
+    pub fn clear_poison(&self) {
+        if !self.is_dangling() {
+            unsafe { (*alias::header(self.ptr)).set_flag(POISONED, false) };
+        }
+    }
+
+    /// Adds a hook that runs when the last strong reference to this value drops while weak references
+    /// remain, right before the value is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    #[track_caller]
+    pub fn on_orphan<F: FnOnce() + 'static>(&self, hook: F) {
+        if self.is_none() {
+            panic!("hook on Rcn with none value");
+        }
+        hooks::add_orphan_hook(alias::header(self.ptr), Box::new(hook));
+    }
+
+    /// Adds a hook that runs when the memory of this box is released, after the last strong and weak
+    /// references are gone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Rcn` is none.
+    #[track_caller]
+    pub fn on_free<F: FnOnce() + 'static>(&self, hook: F) {
+        if self.is_none() {
+            panic!("hook on Rcn with none value");
+        }
+        hooks::add_free_hook(alias::header(self.ptr), Box::new(hook));
+    }
+
+    /// Turns this handle into a [`UniqueRcn`] if it [`is_unique`](#method.is_unique), so the value can be
+    /// mutated as unshared again. Otherwise returns the handle unchanged. Aliasing handles are never
+    /// unique in this sense, since their value belongs to another box.
+    ///
+    /// [`UniqueRcn`]: struct.UniqueRcn.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::{Rcn, UniqueRcn};
+    ///
+    /// let shared = Rcn::new(vec![1, 2]);
+    /// let mut unique = Rcn::try_into_unique(shared).unwrap();
+    /// unique.push(3);
+    /// let shared = UniqueRcn::into_shared(unique);
+    /// let other = shared.share();
+    /// assert!(Rcn::try_into_unique(shared).is_err());
+    /// # drop(other);
+    /// ```
+    pub fn try_into_unique(this: Self) -> Result<UniqueRcn<T>, Self> {
+        if this.is_unique() && !alias::is_alias(this.ptr) && !this.frozen() {
+            Ok(UniqueRcn::from_unique(this))
+        } else {
+            Err(this)
+        }
+    }
+
+    /// Borrows this handle as an [`RcnBorrow`], which can be copied and passed around without touching
+    /// the counts.
+    ///
+    /// [`RcnBorrow`]: struct.RcnBorrow.html
+    #[inline]
+    pub fn as_borrow(&self) -> RcnBorrow<'_, T> {
+        RcnBorrow::new(self)
+    }
+
+    /// Returns an `Rcn<U>` to the part of the value selected by `f`. The new handle shares the box of
+    /// `this`: it counts as a strong reference to it and keeps the whole value alive, and its
+    /// [`downgrade`](#method.downgrade) makes weak references to it. It can be passed wherever an `Rcn<U>`
+    /// is expected, but [`take`](#method.take) and [`try_unwrap`](#method.try_unwrap) always fail on it,
+    /// since the value cannot be moved out of its parent.
+    ///
+    /// `f` borrows the value mutably, since the alias can change the selected part through `DerefMut`,
+    /// [`set`](#method.set) or [`replace`](#method.replace).
+    ///
+    /// Unlike [`project`](#method.project), which returns a dedicated handle type, each alias allocates a
+    /// small box recording where the value is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `this` is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// struct Player {
+    ///     name: String,
+    ///     score: u32,
+    /// }
+    ///
+    /// fn announce(name: Rcn<String>) -> String {
+    ///     format!("{} joined", name)
+    /// }
+    ///
+    /// let mut player = Rcn::new(Player { name: String::from("ada"), score: 0 });
+    /// let name = Rcn::alias(&mut player, |p| &mut p.name);
+    /// assert_eq!(player.strong_count(), 2);
+    /// assert_eq!(announce(name), "ada joined");
+    /// assert_eq!(player.score, 0);
+    /// ```
+    #[track_caller]
+    pub fn alias<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(this: &mut Self, f: F) -> Rcn<U> {
+        if this.is_none() {
+            panic!("alias of Rcn with none value");
+        }
+        // Frozen values can be aliased too; the alias shares the frozen flag of the box.
+        let ptr = alias::create(this.ptr, f(unsafe { &mut *this.value() }));
+        this.inc_strong();
+        Rcn::from_inner(ptr)
+    }
+
+    /// Returns a handle to the part of the value selected by `f`, which shares the strong count of `this`
+    /// and keeps the whole value alive. Projecting a none `Rcn` yields a none [`RcnRef`], without calling
+    /// `f`. The `RcnRef` calls `f` again on every access, so it follows changes to the value.
+    ///
+    /// [`RcnRef`]: struct.RcnRef.html
+    ///
+    /// # Panics
+    ///
+    /// Dereferencing the `RcnRef` panics if `f` panics on the value at that time, for example an index
+    /// that was valid when projecting but is out of bounds after the value shrank.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let point = Rcn::new((3, 4));
+    /// let x = Rcn::project(&point, |p| &p.0);
+    /// drop(point);
+    /// assert_eq!(*x, 3);
+    /// ```
+    pub fn project<U: ?Sized, F: Fn(&T) -> &U + 'static>(this: &Self, f: F) -> RcnRef<T, U> {
+        RcnRef::new(this, f)
+    }
+
+    /// Returns the outstanding strong and weak handles to this value and where they were created,
+    /// oldest first. Handles consumed by [`into_raw`](#method.into_raw) stay listed, since they still
+    /// hold a strong reference.
+    ///
+    /// Requires the `track-holders` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let five = Rcn::new(5);
+    /// let stray = five.share();
+    ///
+    /// for holder in five.holders() {
+    ///     println!("{}", holder); // Strong #1 created at src/main.rs:6:12
+    /// }
+    /// assert_eq!(five.holders().len(), 2);
+    /// # drop(stray);
+    /// ```
+    #[cfg(feature = "track-holders")]
+    pub fn holders(&self) -> Vec<debug::Holder> {
+        if self.is_dangling() {
+            Vec::new()
+        } else {
+            debug::holders_of(alias::header(self.ptr) as usize)
+        }
+    }
+
+    /// Wraps `ptr`, taking over one strong reference to it.
+    #[inline]
+    #[track_caller]
+    fn from_inner(ptr: NonNull<RcnBox<T>>) -> Rcn<T> {
+        if alias::is_alias(ptr) {
+            alias::add_handle(ptr);
+        }
+        Rcn {
+            ptr,
             phantom: PhantomData,
-        };
-        
-        mem::forget(ptr);
+            #[cfg(feature = "track-holders")]
+            holder: track_holder(ptr, debug::HolderKind::Strong),
+            #[cfg(feature = "checked")]
+            generation: RcnBox::generation_of(ptr),
+        }
+    }
 
-        rcn
+    /// Returns `true` if this is a none handle that never pointed to a box.
+    #[inline]
+    fn is_dangling(&self) -> bool {
+        RcnBox::is_dangling(self.ptr)
+    }
+
+    /// Returns the header holding the counts, which is the parent's for an aliasing handle. The handle
+    /// must not be dangling.
+    #[inline(always)]
+    fn header(&self) -> &RcnBox<()> {
+        unsafe { &*alias::header(self.ptr) }
+    }
+
+    /// Returns the value this handle points to. The handle must not be dangling.
+    #[inline(always)]
+    fn value(&self) -> *mut T {
+        alias::value(self.ptr)
+    }
+
+    /// Returns `true` if the box is frozen. The handle must not be dangling.
+    #[inline(always)]
+    fn frozen(&self) -> bool {
+        unsafe { (*alias::header(self.ptr)).has_flag(FROZEN) }
+    }
+
+    /// Drops the cached hash of the value, which is about to change.
+    #[inline]
+    fn unhash(&self) {
+        let header = alias::header(self.ptr);
+        if unsafe { (*header).has_flag(HASHED) } {
+            hashed::forget(header);
+        }
+    }
+
+    /// Removes this handle from the holder records, before it is dropped or forgotten.
+    #[inline]
+    fn untrack(&mut self) {
+        #[cfg(feature = "track-holders")]
+        debug::untrack(alias::header(self.ptr) as usize, mem::replace(&mut self.holder, 0));
+    }
+
+    /// Panics if the box behind this handle was released.
+    #[cfg(feature = "checked")]
+    #[track_caller]
+    fn verify(&self) {
+        checked::verify("Rcn", self.header().canary.get(), self.header().generation.get(), self.generation)
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn strong(&self) -> usize {
-        if self.ptr.is_null() {
+        if self.is_dangling() {
             0
         } else {
-            unsafe { self.ptr.as_ref().unwrap().strong.get() }
+            #[cfg(feature = "checked")]
+            self.verify();
+            self.header().strong.get()
         }
         
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn inc_strong(&self) {
+        let strong = count::increment(self.strong(), usize::MAX);
+        self.header().strong.set(strong);
+    }
 
-        if self.strong() == usize::max_value() {
-            panic!("abort inc strong");
-        }
-        unsafe { self.ptr.as_ref().unwrap().strong.set(self.strong() + 1); }
+    #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn try_inc_strong(&self) -> Result<(), OverflowError> {
+        let strong = count::try_increment(self.strong(), usize::MAX)?;
+        self.header().strong.set(strong);
+        Ok(())
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn dec_strong(&self) {
-        if self.strong() == usize::min_value() {
-            panic!("abort dec strong");
-        }
-
-        unsafe { 
-            self.ptr.as_ref().unwrap().strong.set(self.strong() - 1);
-        }
+        let strong = count::decrement(self.strong(), usize::MAX, "strong");
+        self.header().strong.set(strong);
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn weak(&self) -> usize {
-        if self.ptr.is_null() {
+        if self.is_dangling() {
             0
         } else {
-            unsafe { self.ptr.as_ref().unwrap().weak.get() }
+            #[cfg(feature = "checked")]
+            self.verify();
+            self.header().weak_count()
         }
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn inc_weak(&self) {
-        if self.weak() == usize::max_value() {
-            panic!("abort inc weak");
-        }
-        unsafe { self.ptr.as_ref().unwrap().weak.set(self.weak() + 1);}
+        let weak = count::increment(self.weak(), WEAK_MAX);
+        self.header().set_weak_count(weak);
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn try_inc_weak(&self) -> Result<(), OverflowError> {
+        let weak = count::try_increment(self.weak(), WEAK_MAX)?;
+        self.header().set_weak_count(weak);
+        Ok(())
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn dec_weak(&self) {
-        if self.weak() == usize::min_value() {
-            panic!("abort dec weak");
-        }
-        unsafe { self.ptr.as_ref().unwrap().weak.set(self.weak() - 1); }
+        let weak = count::decrement(self.weak(), WEAK_MAX, "weak");
+        self.header().set_weak_count(weak);
     }
 }
 
@@ -485,10 +1326,11 @@ impl<T: ?Sized> Rcn<T> {
 impl<T: Clone> Rcn<T> {
     ///Get a clone of internal data
     #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn get(&self) -> T {
         if self.is_some() {
             unsafe {
-                self.ptr.as_ref().unwrap().value.clone()
+                (*self.value()).clone()
             }
         } else {
             panic!("access (get) of none rcn!");
@@ -496,10 +1338,15 @@ impl<T: Clone> Rcn<T> {
     }
 
     #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn set(&mut self, data: &T) {
         if self.is_some() {
+            if self.frozen() {
+                panic!("write (set) in frozen rcn!");
+            }
+            self.unhash();
             unsafe {
-                self.ptr.as_mut().unwrap().value = data.clone();
+                *self.value() = data.clone();
             }
         } else {
             panic!("write (set) in none rcn!\n \t help: Use Rcn:new(...) to none pointers");
@@ -512,14 +1359,7 @@ impl<T: Clone> Clone for Rcn<T> {
     fn clone(&self) -> Rcn<T> {
         if self.is_some() {
             unsafe {
-                Rcn::<T> {
-                    ptr: Box::into_raw(Box::new(RcnBox {
-                            strong: Cell::new(1),
-                            weak:  Cell::new(0),
-                            value: self.ptr.as_ref().unwrap().value.clone(),
-                        })),
-                    phantom: PhantomData,
-                }
+                Rcn::from_inner(RcnBox::allocate((*self.value()).clone()))
             }
         } else {
             Rcn::none()
@@ -529,15 +1369,31 @@ impl<T: Clone> Clone for Rcn<T> {
 
 impl <T: ?Sized> Drop for Rcn<T> {
     fn drop(&mut self) {
+        self.untrack();
+        let aliasing = alias::is_alias(self.ptr);
         if self.is_some() {
             self.dec_strong();
-            unsafe { 
-                if self.strong() == 0 {
-                    ptr::drop_in_place(self.ptr);
-                    System.dealloc(self.ptr as *mut u8, Layout::for_value(self.ptr.as_ref().unwrap())); 
+            if self.strong() == 0 {
+                if aliasing {
+                    unsafe { alias::release_parent(self.ptr) };
+                } else {
+                    unsafe { drops::release(self.ptr) };
+                }
+            }
+        } else if !self.is_dangling() {
+            // The box was torn down by its `RcnScope`, which turned this handle into a weak reference.
+            self.dec_weak();
+            if self.weak() == 0 {
+                if aliasing {
+                    unsafe { alias::deallocate_parent(self.ptr) };
+                } else {
+                    unsafe { RcnBox::deallocate(self.ptr.as_ptr()) };
                 }
             }
         }
+        if aliasing {
+            unsafe { alias::remove_handle(self.ptr) };
+        }
     }
 }
 
@@ -545,10 +1401,11 @@ impl<T: ?Sized> Deref for Rcn<T> {
     type Target = T;
 
     #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn deref(&self) -> &T {
         if self.is_some() {
             unsafe {
-                &self.ptr.as_ref().unwrap().value
+                &*self.value()
             }
         } else {
             panic!("deref of none rcn!");
@@ -559,10 +1416,15 @@ impl<T: ?Sized> Deref for Rcn<T> {
 impl<T: ?Sized> DerefMut for Rcn<T> {
     
     #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn deref_mut(&mut self) -> &mut T {
         if self.is_some() {
+            if self.frozen() {
+                panic!("deref_mut of frozen rcn!");
+            }
+            self.unhash();
             unsafe {
-                &mut self.ptr.as_mut().unwrap().value
+                &mut *self.value()
             }
         } else {
             panic!("deref_mut of none rcn!");
@@ -589,6 +1451,7 @@ impl<T: Default> Default for Rcn<T> {
     }
 }
 
+#[allow(clippy::partialeq_ne_impl)]
 impl<T: ?Sized + PartialEq> PartialEq for Rcn<T> {
 
     #[inline(always)]
@@ -634,7 +1497,7 @@ impl<T: ?Sized + PartialOrd> PartialOrd for Rcn<T> {
 
 impl<T: ?Sized> AsRef<T> for Rcn<T> {
     fn as_ref(&self) -> &T {
-        &**self
+        self
     }
 }
 
@@ -651,6 +1514,7 @@ impl<T> From<T> for Rcn<T> {
 }
 
 
+#[allow(clippy::needless_maybe_sized)]
 impl<T: ?Sized> From<Box<T>> for Rcn<T> where T: Clone {
     #[inline]
     fn from(v: Box<T>) -> Rcn<T> {
@@ -665,6 +1529,7 @@ impl<T: ?Sized> From<Box<T>> for Rcn<T> where T: Clone {
     }
 }
 
+#[allow(clippy::needless_maybe_sized)]
 impl<T: ?Sized> From<Rc<T>> for Rcn<T> where T: Clone {
     #[inline]
     fn from(v: Rc<T>) -> Rcn<T> {
@@ -675,6 +1540,71 @@ impl<T: ?Sized> From<Rc<T>> for Rcn<T> where T: Clone {
     }
 }
 
+impl<T: Clone> From<&[T]> for Rcn<[T]> {
+    /// Allocates a reference-counted slice holding clones of the items of `v`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let slice: Rcn<[i32]> = Rcn::from(&[1, 2, 3][..]);
+    /// assert_eq!(&*slice, &[1, 2, 3]);
+    /// ```
+    #[track_caller]
+    fn from(v: &[T]) -> Rcn<[T]> {
+        unsafe {
+            let ptr = RcnBox::from_slice(v);
+            RcnBox::register(ptr);
+            Rcn::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Rcn<[T]> {
+    /// Allocates a reference-counted slice and moves the items of `v` into it.
+    #[track_caller]
+    fn from(mut v: Vec<T>) -> Rcn<[T]> {
+        unsafe {
+            let ptr = RcnBox::<[T]>::allocate_slice(v.len());
+            ptr::copy_nonoverlapping(v.as_ptr(), ptr::addr_of_mut!((*ptr).value) as *mut T, v.len());
+            v.set_len(0);
+            RcnBox::register(ptr);
+            Rcn::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl From<&str> for Rcn<str> {
+    /// Allocates a reference-counted string slice holding a copy of `v`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    ///
+    /// let shared: Rcn<str> = Rcn::from("statue");
+    /// assert_eq!(&*shared, "statue");
+    /// ```
+    #[track_caller]
+    fn from(v: &str) -> Rcn<str> {
+        unsafe {
+            let ptr = RcnBox::from_slice(v.as_bytes()) as *mut RcnBox<str>;
+            RcnBox::register(ptr);
+            Rcn::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl From<String> for Rcn<str> {
+    #[track_caller]
+    fn from(v: String) -> Rcn<str> {
+        Rcn::from(&v[..])
+    }
+}
+
 // impl Rcn<dyn Any> {
 //     #[inline]
 //     /// Attempt to downcast the `Rc<dyn Any>` to a concrete type.
@@ -712,21 +1642,22 @@ impl<T: ?Sized> From<Rc<T>> for Rcn<T> where T: Clone {
 
 #[allow(dead_code)]
 pub struct Weakn<T: ?Sized> {
-    ptr: *mut RcnBox<T>,
+    ptr: NonNull<RcnBox<T>>,
+    #[cfg(feature = "track-holders")]
+    holder: u64,
+    #[cfg(feature = "checked")]
+    generation: u64,
 }
 
 #[allow(dead_code)]
 impl<T> Weakn<T> {
+    /// Constructs a `Weakn<T>` with none value.
     pub fn new() -> Weakn<T> {
-        Weakn {
-            ptr: ptr::null_mut(),
-        }
+        Weakn::from_inner(RcnBox::dangling())
     }
 
     pub fn none() -> Weakn<T> {
-        Weakn::<T> {
-            ptr: 0 as *mut RcnBox<T>,
-        }
+        Weakn::from_inner(RcnBox::dangling())
     }
 }
 
@@ -734,10 +1665,11 @@ impl<T> Weakn<T> {
 impl<T: ?Sized> Weakn<T> {
 
     #[inline]
+    #[track_caller]
     pub fn share(&self) -> Weakn<T> {
         if self.is_some() {
             self.inc_weak();
-            Weakn { ptr: self.ptr, }
+            Weakn::from_inner(self.inner())
         } else {
             panic!("share of Weakn with none value");
         }
@@ -745,76 +1677,196 @@ impl<T: ?Sized> Weakn<T> {
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn is_none(&self) -> bool {
-        self.strong() == 0 || self.ptr.is_null()
+        self.strong() == 0 || self.is_dangling()
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     pub fn is_some(&self) -> bool {
-        self.strong() > 0 && !self.ptr.is_null()
+        self.strong() > 0 && !self.is_dangling()
     }
 
+    #[track_caller]
     pub fn upgrade(&self) -> Option<Rcn<T>> {
-        unsafe { 
-            if self.ptr.as_ref().unwrap().strong.get() == 0 {
-                return None
-            }
+        if self.is_none() {
+            return None
         }
         self.inc_strong();
-        Some(Rcn { ptr: self.ptr, phantom: PhantomData })
+        Some(Rcn::from_inner(self.inner()))
     }
 
-       #[inline]
-    fn strong(&self) -> usize {
-        unsafe { self.ptr.as_ref().unwrap().strong.get() }
+    /// Registers `callback` to run once the strong count reaches zero, right after the value is dropped.
+    /// The callback is removed when the returned token is cancelled or when this `Weakn` drops; handles
+    /// made from it by [`share`](#method.share) have callbacks of their own. If the value is already gone,
+    /// the callback runs immediately.
+    ///
+    /// Callbacks only run once no strong reference is left, so they cannot upgrade a `Weakn` to the
+    /// expired value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rcn;
+    /// use rcn::Rcn;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// let expired = Rc::new(Cell::new(false));
+    /// let session = Rcn::new(42);
+    /// let mut entry = session.downgrade();
+    /// let flag = expired.clone();
+    /// entry.on_expire(move || flag.set(true));
+    ///
+    /// drop(session);
+    /// assert!(expired.get());
+    /// ```
+    pub fn on_expire<F: FnOnce() + 'static>(&mut self, callback: F) -> ExpiryToken {
+        if self.is_none() {
+            callback();
+            return ExpiryToken::expired();
+        }
+        self.ptr = hooks::listen(self.ptr);
+        hooks::add_expiry(self.ptr, Box::new(callback))
     }
 
+    /// Wraps `ptr`, taking over one weak reference to it.
     #[inline]
-    fn inc_strong(&self) {
-
-        if self.strong() == usize::max_value() {
-            panic!("abort inc strong");
+    #[track_caller]
+    fn from_inner(ptr: NonNull<RcnBox<T>>) -> Weakn<T> {
+        if alias::is_alias(ptr) {
+            alias::add_handle(ptr);
+        }
+        Weakn {
+            ptr,
+            #[cfg(feature = "track-holders")]
+            holder: track_holder(ptr, debug::HolderKind::Weak),
+            #[cfg(feature = "checked")]
+            generation: RcnBox::generation_of(ptr),
         }
-        unsafe { self.ptr.as_ref().unwrap().strong.set(self.strong() + 1); }
     }
 
+    /// Returns `true` if this is a none handle that never pointed to a box.
     #[inline]
-    fn dec_strong(&self) {
-        if self.strong() == usize::min_value(){
-            panic!("abort dec strong");
+    fn is_dangling(&self) -> bool {
+        RcnBox::is_dangling(self.ptr)
+    }
+
+    /// Returns the pointer to the box, or alias box, behind this handle, past its listener record if it
+    /// registered expiry callbacks.
+    #[inline(always)]
+    fn inner(&self) -> NonNull<RcnBox<T>> {
+        hooks::target(self.ptr)
+    }
+
+    /// Returns the header holding the counts, which is the parent's for an aliasing handle. The handle
+    /// must not be dangling.
+    #[inline(always)]
+    fn header(&self) -> &RcnBox<()> {
+        unsafe { &*alias::header(self.inner()) }
+    }
+
+    /// Returns the value this handle points to. The handle must not be dangling.
+    #[inline(always)]
+    fn value(&self) -> *mut T {
+        alias::value(self.inner())
+    }
+
+    /// Returns `true` if the box is frozen. The handle must not be dangling.
+    #[inline(always)]
+    fn frozen(&self) -> bool {
+        unsafe { (*alias::header(self.inner())).has_flag(FROZEN) }
+    }
+
+    /// Panics if the box behind this handle was released.
+    #[cfg(feature = "checked")]
+    #[track_caller]
+    fn verify(&self) {
+        checked::verify("Weakn", self.header().canary.get(), self.header().generation.get(), self.generation)
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn strong(&self) -> usize {
+        if self.is_dangling() {
+            0
+        } else {
+            #[cfg(feature = "checked")]
+            self.verify();
+            self.header().strong.get()
         }
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn inc_strong(&self) {
+        let strong = count::increment(self.strong(), usize::MAX);
+        self.header().strong.set(strong);
+    }
 
-        unsafe { self.ptr.as_ref().unwrap().strong.set(self.strong() - 1); }
+    #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn dec_strong(&self) {
+        let strong = count::decrement(self.strong(), usize::MAX, "strong");
+        self.header().strong.set(strong);
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn weak(&self) -> usize {
-        unsafe { self.ptr.as_ref().unwrap().weak.get() }
+        if self.is_dangling() {
+            0
+        } else {
+            #[cfg(feature = "checked")]
+            self.verify();
+            self.header().weak_count()
+        }
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn inc_weak(&self) {
-        if self.weak() == usize::max_value() {
-            panic!("abort inc weak");
-        }
-        unsafe { self.ptr.as_ref().unwrap().weak.set(self.weak() + 1);}
+        let weak = count::increment(self.weak(), WEAK_MAX);
+        self.header().set_weak_count(weak);
     }
 
     #[inline]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn dec_weak(&self) {
-        if self.weak() == usize::min_value() {
-            panic!("abort dec weak");
-        }
-        unsafe { self.ptr.as_ref().unwrap().weak.set(self.weak() - 1); }
+        let weak = count::decrement(self.weak(), WEAK_MAX, "weak");
+        self.header().set_weak_count(weak);
     }
 }
 
 impl<T: ?Sized> Drop for Weakn<T> {
     fn drop(&mut self) {
+        if self.is_dangling() {
+            return;
+        }
+        #[cfg(feature = "track-holders")]
+        debug::untrack(alias::header(self.inner()) as usize, self.holder);
+        if hooks::is_listening(self.ptr) {
+            self.ptr = unsafe { hooks::unlisten(self.ptr) };
+        }
         self.dec_weak();
-        // if self.weak() == 0 {
-        //     unsafe { GLOBAL.dealloc(self.ptr.cast::<u8>().as_ptr(), Layout::for_value(self.ptr.as_ref())); }
-        // }
+        let aliasing = alias::is_alias(self.ptr);
+        if self.weak() == 0 && self.strong() == 0 {
+            if aliasing {
+                unsafe { alias::deallocate_parent(self.ptr) };
+            } else {
+                unsafe { RcnBox::deallocate(self.ptr.as_ptr()); }
+            }
+        }
+        if aliasing {
+            unsafe { alias::remove_handle(self.ptr) };
+        }
+    }
+}
+
+impl<T> Default for Weakn<T> {
+    fn default() -> Weakn<T> {
+        Weakn::new()
     }
 }
 
@@ -831,6 +1883,7 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for Weakn<T> {
     }
 }
 
+#[allow(clippy::partialeq_ne_impl)]
 impl<T: ?Sized + PartialEq> PartialEq for Weakn<T> {
 
     #[inline(always)]
@@ -878,10 +1931,11 @@ impl<T: ?Sized> Deref for Weakn<T> {
     type Target = T;
 
     #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
     fn deref(&self) -> &T {
         if self.is_some() {
             unsafe {
-                &self.ptr.as_ref().unwrap().value
+                &*self.value()
             }
         } else {
             panic!("deref of none weakn!");
@@ -889,6 +1943,16 @@ impl<T: ?Sized> Deref for Weakn<T> {
     }
 }
 
+#[cfg(feature = "track-holders")]
+#[track_caller]
+fn track_holder<T: ?Sized>(ptr: NonNull<RcnBox<T>>, kind: debug::HolderKind) -> u64 {
+    if RcnBox::is_dangling(ptr) {
+        0
+    } else {
+        debug::track(alias::header(ptr) as usize, kind, std::panic::Location::caller())
+    }
+}
+
 #[allow(unused_imports)]
 #[cfg(test)]
 mod test {
@@ -1096,6 +2160,25 @@ mod test {
         drop(x);
         assert!(w.is_none());
     }
+
+    #[test]
+    #[cfg(not(any(feature = "track-holders", feature = "checked")))]
+    fn pointer_sized_test() {
+        use std::mem::size_of;
+        assert_eq!(size_of::<Rcn<u64>>(), size_of::<*const ()>());
+        assert_eq!(size_of::<Option<Rcn<String>>>(), size_of::<*const ()>());
+        assert_eq!(size_of::<Weakn<u64>>(), size_of::<*const ()>());
+        assert_eq!(size_of::<Option<Weakn<String>>>(), size_of::<*const ()>());
+    }
+
+    #[test]
+    fn none_downgrade_test() {
+        let x: Rcn<i32> = Rcn::none();
+        let w = x.downgrade();
+        assert!(w.is_none());
+        assert!(w.upgrade().is_none());
+        assert!(Rcn::ptr_eq(&x, &Rcn::none()));
+    }
     
     #[test]
     fn test_cowrc_clone_weak() {
@@ -1122,6 +2205,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn name() {
         let mut t1: Rcn<i32> = Rcn::new(100);
         let mut t2: Rcn<i32> = t1.share();
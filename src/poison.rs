@@ -0,0 +1,135 @@
+//! Poisoning: marking a value whose modification was interrupted by a panic.
+
+use std::error::Error;
+use std::fmt;
+use std::thread;
+
+use crate::{RcnBox, POISONED};
+
+/// Poisons the box behind `header` if a panic unwinds while the guard is alive.
+pub(crate) struct Modifying {
+    header: *const RcnBox<()>,
+    panicking: bool,
+}
+
+impl Modifying {
+    pub(crate) fn new(header: *const RcnBox<()>) -> Modifying {
+        Modifying { header, panicking: thread::panicking() }
+    }
+}
+
+impl Drop for Modifying {
+    fn drop(&mut self) {
+        if !self.panicking && thread::panicking() {
+            unsafe { (*self.header).set_flag(POISONED, true) };
+        }
+    }
+}
+
+/// The error of the `try_` accessors of an `Rcn` whose value was poisoned by a panic in
+/// [`Rcn::modify`](struct.Rcn.html#method.modify). It still gives access to the value, which may be only
+/// partly modified.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::Rcn;
+/// use std::panic::{self, AssertUnwindSafe};
+///
+/// let mut totals = Rcn::new(vec![1, 2]);
+/// let _ = panic::catch_unwind(AssertUnwindSafe(|| totals.modify(|t| {
+///     t.push(3);
+///     panic!("interrupted");
+/// })));
+///
+/// let err = totals.try_read().unwrap_err();
+/// assert_eq!(**err.get_ref(), [1, 2, 3]);
+/// totals.clear_poison();
+/// assert!(totals.try_read().is_ok());
+/// ```
+pub struct PoisonError<G> {
+    value: G,
+}
+
+impl<G> PoisonError<G> {
+    pub(crate) fn new(value: G) -> PoisonError<G> {
+        PoisonError { value }
+    }
+
+    /// Returns the value that was poisoned.
+    pub fn into_inner(self) -> G {
+        self.value
+    }
+
+    /// Returns a reference to the value that was poisoned.
+    pub fn get_ref(&self) -> &G {
+        &self.value
+    }
+
+    /// Returns a mutable reference to the value that was poisoned.
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.value
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rcn value poisoned by a panic during modify")
+    }
+}
+
+impl<G> Error for PoisonError<G> {}
+
+#[cfg(test)]
+mod test {
+    use crate::Rcn;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn interrupt(rcn: &mut Rcn<Vec<u32>>) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| rcn.modify(|v| {
+            v.push(0);
+            panic!("halfway");
+        })));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn panics_poison_every_holder() {
+        let mut a = Rcn::new(vec![1]);
+        let mut b = a.share();
+        assert_eq!(a.modify(|v| { v.push(2); v.len() }), 2);
+        assert!(!b.is_poisoned());
+
+        interrupt(&mut a);
+        assert!(b.is_poisoned());
+        assert_eq!(*b.try_write().unwrap_err().into_inner(), [1, 2, 0]);
+        let skipped = b.try_modify(|v| v.clear());
+        assert_eq!(*skipped.unwrap_err().get_ref(), &[1, 2, 0]);
+        // Plain access still works, as with `PoisonError::into_inner` on a mutex.
+        assert_eq!(b.len(), 3);
+
+        a.clear_poison();
+        assert!(!b.is_poisoned());
+        assert_eq!(b.try_modify(|v| v.pop()).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn poison_goes_with_the_box() {
+        let mut doc = Rcn::new((String::from("title"), vec![1]));
+        let mut pages = Rcn::alias(&mut doc, |d| &mut d.1);
+        interrupt(&mut pages);
+        assert!(doc.is_poisoned());
+        drop((doc, pages));
+
+        let fresh = Rcn::new((String::new(), vec![2]));
+        assert!(!fresh.is_poisoned());
+        assert!(!Rcn::<u8>::none().is_poisoned());
+    }
+}
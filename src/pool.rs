@@ -0,0 +1,251 @@
+//! A recycling allocator for `Rcn` boxes.
+
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use crate::region::{self, Owner};
+use crate::{Rcn, RcnBox};
+
+/// A pool of `Rcn<T>` boxes. A box allocated by the pool goes back to the pool's free list when its last
+/// strong and weak references drop, and the next [`alloc`](#method.alloc) reuses it instead of calling the
+/// global allocator.
+///
+/// The free list can be bounded with [`with_limit`](#method.with_limit); boxes released to a full pool are
+/// freed. Dropping the pool frees its free list, and boxes still in use are freed as usual when their last
+/// reference drops.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::RcnPool;
+///
+/// let pool = RcnPool::new();
+/// let first = pool.alloc(1.5f64);
+/// drop(first);
+/// let second = pool.alloc(2.5);
+/// assert_eq!(*second, 2.5);
+///
+/// let stats = pool.stats();
+/// assert_eq!((stats.hits, stats.misses), (1, 1));
+/// ```
+pub struct RcnPool<T> {
+    shared: Rc<Shared>,
+    phantom: PhantomData<T>,
+}
+
+/// Usage statistics of an [`RcnPool`](struct.RcnPool.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Allocations served from the free list.
+    pub hits: usize,
+    /// Allocations that had to call the global allocator.
+    pub misses: usize,
+    /// The largest number of boxes in use at the same time.
+    pub high_water: usize,
+    /// Boxes currently in use.
+    pub outstanding: usize,
+    /// Boxes currently in the free list.
+    pub available: usize,
+}
+
+struct Shared {
+    layout: Layout,
+    limit: Option<usize>,
+    free: RefCell<Vec<*mut u8>>,
+    closed: Cell<bool>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+    high_water: Cell<usize>,
+    outstanding: Cell<usize>,
+}
+
+impl Shared {
+    /// Returns every box of the free list to the global allocator.
+    fn shrink(&self) {
+        let free = self.free.replace(Vec::new());
+        for mem in free {
+            region::unclaim(mem);
+            unsafe { dealloc(mem, self.layout) };
+        }
+    }
+}
+
+impl Owner for Shared {
+    unsafe fn release(&self, ptr: *mut u8, layout: Layout) {
+        debug_assert!(layout == self.layout);
+        self.outstanding.set(self.outstanding.get() - 1);
+        let mut free = self.free.borrow_mut();
+        if self.closed.get() || self.limit.is_some_and(|limit| free.len() >= limit) {
+            drop(free);
+            region::unclaim(ptr);
+            dealloc(ptr, layout);
+        } else {
+            free.push(ptr);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // Only reached with a non-empty free list if the pool itself was leaked.
+        for &mem in self.free.get_mut().iter() {
+            unsafe { dealloc(mem, self.layout) };
+        }
+    }
+}
+
+impl<T> RcnPool<T> {
+    /// Constructs an empty pool with an unbounded free list.
+    pub fn new() -> RcnPool<T> {
+        RcnPool::with_shared(None)
+    }
+
+    /// Constructs an empty pool keeping at most `limit` boxes in its free list.
+    pub fn with_limit(limit: usize) -> RcnPool<T> {
+        RcnPool::with_shared(Some(limit))
+    }
+
+    fn with_shared(limit: Option<usize>) -> RcnPool<T> {
+        RcnPool {
+            shared: Rc::new(Shared {
+                layout: Layout::new::<RcnBox<T>>(),
+                limit,
+                free: RefCell::new(Vec::new()),
+                closed: Cell::new(false),
+                hits: Cell::new(0),
+                misses: Cell::new(0),
+                high_water: Cell::new(0),
+                outstanding: Cell::new(0),
+            }),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Constructs a new `Rcn<T>` in a box of this pool.
+    #[track_caller]
+    pub fn alloc(&self, value: T) -> Rcn<T> {
+        let shared = &*self.shared;
+        let recycled = shared.free.borrow_mut().pop();
+        let mem = match recycled {
+            Some(mem) => {
+                shared.hits.set(shared.hits.get() + 1);
+                mem
+            }
+            None => {
+                shared.misses.set(shared.misses.get() + 1);
+                let mem = unsafe { alloc(shared.layout) };
+                if mem.is_null() {
+                    handle_alloc_error(shared.layout);
+                }
+                region::claim(mem, shared.layout.size(), self.shared.clone());
+                mem
+            }
+        };
+        let outstanding = shared.outstanding.get() + 1;
+        shared.outstanding.set(outstanding);
+        shared.high_water.set(shared.high_water.get().max(outstanding));
+        unsafe {
+            let ptr = mem as *mut RcnBox<T>;
+            ptr::write(ptr, RcnBox::owned(value));
+            RcnBox::register(ptr);
+            Rcn::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+
+    /// Returns the usage statistics of this pool.
+    pub fn stats(&self) -> PoolStats {
+        let shared = &*self.shared;
+        PoolStats {
+            hits: shared.hits.get(),
+            misses: shared.misses.get(),
+            high_water: shared.high_water.get(),
+            outstanding: shared.outstanding.get(),
+            available: shared.free.borrow().len(),
+        }
+    }
+
+    /// Returns the largest number of boxes the free list keeps, if bounded.
+    pub fn limit(&self) -> Option<usize> {
+        self.shared.limit
+    }
+
+    /// Frees every box in the free list. Boxes in use are not affected.
+    pub fn shrink_to_fit(&self) {
+        self.shared.shrink();
+    }
+}
+
+impl<T> Drop for RcnPool<T> {
+    fn drop(&mut self) {
+        self.shared.closed.set(true);
+        self.shared.shrink();
+    }
+}
+
+impl<T> Default for RcnPool<T> {
+    fn default() -> RcnPool<T> {
+        RcnPool::new()
+    }
+}
+
+impl<T> fmt::Debug for RcnPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcnPool").field("limit", &self.limit()).field("stats", &self.stats()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boxes_are_recycled() {
+        let pool = RcnPool::new();
+        let a = pool.alloc(String::from("a"));
+        let w = a.downgrade();
+        let b = pool.alloc(String::from("b"));
+        let address = &*a as *const String;
+        drop(a);
+        assert_eq!(pool.stats().available, 0);
+        drop(w);
+        assert_eq!(pool.stats().available, 1);
+
+        let c = pool.alloc(String::from("c"));
+        assert_eq!(&*c as *const String, address);
+        assert_eq!(*b, "b");
+        assert_eq!(pool.stats(), PoolStats { hits: 1, misses: 2, high_water: 2, outstanding: 2, available: 0 });
+
+        let mut d = c.share();
+        drop(c);
+        assert_eq!(d.take(), Some(String::from("c")));
+        assert_eq!(Rcn::try_unwrap(b), Ok(String::from("b")));
+        assert_eq!(pool.stats().available, 2);
+        pool.shrink_to_fit();
+        assert_eq!(pool.stats().available, 0);
+    }
+
+    #[test]
+    fn limit_bounds_the_free_list() {
+        let pool = RcnPool::with_limit(1);
+        let handles: Vec<Rcn<u32>> = (0..4).map(|i| pool.alloc(i)).collect();
+        drop(handles);
+        assert_eq!(pool.stats().available, 1);
+        assert_eq!(pool.limit(), Some(1));
+    }
+
+    #[test]
+    fn outstanding_boxes_outlive_the_pool() {
+        let pool = RcnPool::new();
+        let a = pool.alloc(vec![1, 2, 3]);
+        let w = a.downgrade();
+        drop(pool);
+        assert_eq!(*w.upgrade().unwrap(), vec![1, 2, 3]);
+        drop(a);
+        assert!(w.upgrade().is_none());
+    }
+}
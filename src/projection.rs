@@ -0,0 +1,224 @@
+//! Owning projections: handles to a part of a shared value that keep the whole value alive.
+
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::{Rcn, Weakn};
+
+/// A strong handle to a `U` inside the value of an `Rcn<T>`, created by
+/// [`Rcn::project`](struct.Rcn.html#method.project).
+///
+/// An `RcnRef` holds a strong reference to the parent box, so the parent cannot be taken or unwrapped while
+/// it lives, and derefs to the projected part. Projecting a none `Rcn` yields a none `RcnRef`.
+///
+/// The projection keeps no pointer into the value: it runs again on every access, so it still finds the
+/// projected part after the parent's value is changed through [`set`](struct.Rcn.html#method.set) or
+/// `DerefMut`.
+///
+/// # Panics
+///
+/// Dereferencing panics if the projection is none, and if the projection itself panics on the current
+/// value, for example `|v| &v[1..]` once the parent vector was emptied through another handle.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, RcnRef};
+///
+/// struct Config {
+///     database: Database,
+/// }
+///
+/// struct Database {
+///     url: String,
+/// }
+///
+/// let config = Rcn::new(Config { database: Database { url: String::from("postgres://db") } });
+/// let database: RcnRef<Config, Database> = Rcn::project(&config, |c| &c.database);
+/// let url = RcnRef::project(&database, |d| d.url.as_str());
+/// drop((config, database));
+/// assert_eq!(&*url, "postgres://db");
+/// ```
+pub struct RcnRef<T: ?Sized, U: ?Sized> {
+    /// The parent and the projection, or `None` if projected from a none `Rcn`.
+    inner: Option<(Rcn<T>, Projection<T, U>)>,
+}
+
+/// A weak handle to a projection, created by [`RcnRef::downgrade`](struct.RcnRef.html#method.downgrade).
+pub struct WeaknRef<T: ?Sized, U: ?Sized> {
+    inner: Option<(Weakn<T>, Projection<T, U>)>,
+}
+
+/// Selects the projected part of the parent's value, shared by the handles to a projection.
+type Projection<T, U> = Rc<dyn Fn(&T) -> &U>;
+
+impl<T: ?Sized, U: ?Sized> RcnRef<T, U> {
+    /// Projects the value of `owner` through `f`.
+    pub(crate) fn new<F: Fn(&T) -> &U + 'static>(owner: &Rcn<T>, f: F) -> RcnRef<T, U> {
+        if owner.is_none() {
+            return RcnRef { inner: None };
+        }
+        RcnRef { inner: Some((owner.share(), Rc::new(f))) }
+    }
+
+    /// Projects further into the projected part, sharing the same parent.
+    pub fn project<V: ?Sized, F: Fn(&U) -> &V + 'static>(this: &Self, f: F) -> RcnRef<T, V>
+    where T: 'static, U: 'static {
+        match this.inner {
+            Some((ref owner, ref first)) if owner.is_some() => {
+                let first = first.clone();
+                let projection: Projection<T, V> = Rc::new(move |value: &T| f(first(value)));
+                RcnRef { inner: Some((owner.share(), projection)) }
+            }
+            _ => RcnRef { inner: None },
+        }
+    }
+
+    /// Makes another handle to the same projection.
+    #[inline]
+    #[track_caller]
+    pub fn share(&self) -> RcnRef<T, U> {
+        RcnRef { inner: self.inner.as_ref().map(|(owner, projection)| (owner.share(), projection.clone())) }
+    }
+
+    /// Makes a weak handle to this projection.
+    #[inline]
+    pub fn downgrade(&self) -> WeaknRef<T, U> {
+        WeaknRef { inner: self.inner.as_ref().map(|(owner, projection)| (owner.downgrade(), projection.clone())) }
+    }
+
+    /// Gets the number of strong pointers to the parent, projections included.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        self.inner.as_ref().map_or(0, |(owner, _)| owner.strong_count())
+    }
+
+    /// Returns `true` if the projection has none value.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.inner.as_ref().is_none_or(|(owner, _)| owner.is_none())
+    }
+
+    /// Returns `true` if the projection has some value.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        !self.is_none()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> WeaknRef<T, U> {
+    /// Returns a strong handle to the projection if the parent is still alive.
+    pub fn upgrade(&self) -> Option<RcnRef<T, U>> {
+        let (owner, projection) = self.inner.as_ref()?;
+        owner.upgrade().map(|owner| RcnRef { inner: Some((owner, projection.clone())) })
+    }
+
+    /// Returns `true` if the parent is gone or the projection was made from a none `Rcn`.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.inner.as_ref().is_none_or(|(owner, _)| owner.is_none())
+    }
+
+    /// Returns `true` if the parent is still alive.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        !self.is_none()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Deref for RcnRef<T, U> {
+    type Target = U;
+
+    /// Runs the projection on the current value of the parent. Panics if the projection is none, or if the
+    /// projection panics on that value.
+    #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &U {
+        match self.inner {
+            Some((ref owner, ref projection)) if owner.is_some() => projection(owner),
+            _ => panic!("deref of none rcn!"),
+        }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + fmt::Display> fmt::Display for RcnRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for RcnRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> fmt::Debug for WeaknRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(WeaknRef)")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Pair {
+        left: String,
+        right: Vec<u8>,
+    }
+
+    #[test]
+    fn projections_keep_the_parent() {
+        let mut pair = Rcn::new(Pair { left: String::from("left"), right: vec![1, 2, 3] });
+        let left = Rcn::project(&pair, |p| &p.left);
+        let tail = RcnRef::project(&Rcn::project(&pair, |p| &p.right), |r| &r[1..]);
+        assert_eq!(left.strong_count(), 3);
+        assert!(pair.take().is_none());
+
+        let weak = tail.downgrade();
+        drop(pair);
+        assert_eq!(&*left, "left");
+        assert_eq!(*weak.upgrade().unwrap(), [2, 3]);
+        drop((left, tail));
+        assert!(weak.upgrade().is_none());
+        assert!(weak.is_none());
+    }
+
+    #[test]
+    fn projections_follow_changes() {
+        let mut pair = Rcn::new(Pair { left: String::from("left"), right: vec![1, 2, 3] });
+        let tail = RcnRef::project(&Rcn::project(&pair, |p| &p.right), |r| &r[1..]);
+        pair.right = vec![7; 64];
+        assert_eq!(tail.len(), 63);
+        *pair = Pair { left: String::new(), right: vec![4, 5] };
+        assert_eq!(*tail.downgrade().upgrade().unwrap(), [5]);
+    }
+
+    #[test]
+    fn projecting_none() {
+        let none: Rcn<Pair> = Rcn::none();
+        let left = Rcn::project(&none, |_| -> &String { unreachable!() });
+        assert!(left.is_none());
+        assert!(RcnRef::project(&left, |l| l.as_str()).is_none());
+        assert!(left.downgrade().upgrade().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "deref of none rcn!")]
+    fn deref_of_none_projection() {
+        let none: Rcn<Pair> = Rcn::none();
+        let _ = Rcn::project(&none, |p| &p.right).len();
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn projection_panics_after_the_value_shrank() {
+        let mut pair = Rcn::new(Pair { left: String::from("left"), right: vec![1, 2, 3] });
+        let tail = RcnRef::project(&Rcn::project(&pair, |p| &p.right), |r| &r[1..]);
+        pair.right.clear();
+        let _ = tail.len();
+    }
+}
@@ -0,0 +1,307 @@
+//! `RcnReader<T>`: a strong handle that can read the value but never change it.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::alias;
+use crate::{Rcn, RcnBox, Weakn, HAS_READERS};
+
+thread_local! {
+    /// Readers by header address, for the boxes flagged with `HAS_READERS`.
+    static COUNTS: RefCell<HashMap<usize, usize>> = RefCell::new(HashMap::new());
+}
+
+/// Counts a new reader to the box at `header`.
+fn add_reader(header: *mut RcnBox<()>) {
+    COUNTS.with(|counts| *counts.borrow_mut().entry(header as usize).or_insert(0) += 1);
+    unsafe { (*header).set_flag(HAS_READERS, true) };
+}
+
+/// Forgets a reader to the box at `header`, clearing the flag with the last one.
+fn remove_reader(header: *mut RcnBox<()>) {
+    let _ = COUNTS.try_with(|counts| {
+        if let Entry::Occupied(mut entry) = counts.borrow_mut().entry(header as usize) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+                unsafe { (*header).set_flag(HAS_READERS, false) };
+            }
+        }
+    });
+}
+
+/// Returns the number of readers to the box at `header`. Boxes that never had one skip the lookup.
+pub(crate) fn readers(header: *mut RcnBox<()>) -> usize {
+    if !unsafe { (*header).has_flag(HAS_READERS) } {
+        return 0;
+    }
+    COUNTS.with(|counts| counts.borrow().get(&(header as usize)).copied().unwrap_or(0))
+}
+
+/// A read-only handle to the value of an `Rcn<T>`, returned by
+/// [`Rcn::share_readonly`](struct.Rcn.html#method.share_readonly).
+///
+/// A reader shares the box and counts of the `Rcn` it came from and sees every change made through it, but
+/// has no `DerefMut`, `set` or `take`, and neither it nor its [`WeakReader`](struct.WeakReader.html)s can
+/// be turned back into an `Rcn`. Readers are counted apart from the other strong handles, so
+/// [`has_writers`](#method.has_writers) tells whether anyone can still change the value.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::Rcn;
+///
+/// let mut progress = Rcn::new(0);
+/// let view = progress.share_readonly();
+/// *progress = 50;
+/// assert_eq!(*view, 50);
+/// assert!(view.has_writers());
+///
+/// drop(progress);
+/// assert!(!view.has_writers());
+/// assert_eq!(*view, 50);
+/// ```
+pub struct RcnReader<T: ?Sized> {
+    rcn: Rcn<T>,
+}
+
+/// A weak handle created by [`RcnReader::downgrade`](struct.RcnReader.html#method.downgrade). It only
+/// upgrades to another `RcnReader`.
+pub struct WeakReader<T: ?Sized> {
+    weak: Weakn<T>,
+}
+
+impl<T: ?Sized> RcnReader<T> {
+    /// Turns `rcn`, which must have some value, into a reader.
+    pub(crate) fn new(rcn: Rcn<T>) -> RcnReader<T> {
+        add_reader(alias::header(rcn.ptr));
+        RcnReader { rcn }
+    }
+
+    /// Makes another reader to the same value.
+    #[inline]
+    #[track_caller]
+    pub fn share(&self) -> RcnReader<T> {
+        RcnReader::new(self.rcn.share())
+    }
+
+    /// Creates a weak handle to the value, which upgrades to a reader.
+    #[inline]
+    #[track_caller]
+    pub fn downgrade(&self) -> WeakReader<T> {
+        WeakReader { weak: self.rcn.downgrade() }
+    }
+
+    /// Gets the number of strong pointers to the value, readers included.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        self.rcn.strong_count()
+    }
+
+    /// Gets the number of weak pointers to the value.
+    #[inline]
+    pub fn weak_count(&self) -> usize {
+        self.rcn.weak_count()
+    }
+
+    /// Gets the number of readers to the value, this one included.
+    #[inline]
+    pub fn reader_count(&self) -> usize {
+        self.rcn.reader_count()
+    }
+
+    /// Gets the number of strong pointers to the value that are not readers.
+    #[inline]
+    pub fn writer_count(&self) -> usize {
+        self.strong_count().saturating_sub(self.reader_count())
+    }
+
+    /// Returns `true` if some `Rcn` can still change the value. Once this returns `false`, the value only
+    /// changes again if a `Weakn` to it is upgraded.
+    #[inline]
+    pub fn has_writers(&self) -> bool {
+        self.writer_count() > 0
+    }
+
+    /// Returns `true` if the value is gone, which only happens when its scope was torn down.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.rcn.is_none()
+    }
+
+    /// Returns `true` if the reader has some value.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.rcn.is_some()
+    }
+
+    /// Returns `true` if both readers point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rcn::ptr_eq(&this.rcn, &other.rcn)
+    }
+}
+
+impl<T: ?Sized> Drop for RcnReader<T> {
+    fn drop(&mut self) {
+        // The box is alive until `rcn` drops right after this.
+        if !self.rcn.is_dangling() {
+            remove_reader(alias::header(self.rcn.ptr));
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RcnReader<T> {
+    type Target = T;
+
+    #[inline(always)]
+    #[cfg_attr(feature = "checked", track_caller)]
+    fn deref(&self) -> &T {
+        &self.rcn
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for RcnReader<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for RcnReader<T> {
+    fn eq(&self, other: &RcnReader<T>) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for RcnReader<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for RcnReader<T> {
+    fn partial_cmp(&self, other: &RcnReader<T>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for RcnReader<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RcnReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RcnReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> WeakReader<T> {
+    /// Constructs a `WeakReader<T>` with none value.
+    pub fn new() -> WeakReader<T> {
+        WeakReader { weak: Weakn::new() }
+    }
+}
+
+impl<T: ?Sized> WeakReader<T> {
+    /// Makes another weak handle to the same value.
+    #[inline]
+    #[track_caller]
+    pub fn share(&self) -> WeakReader<T> {
+        WeakReader { weak: self.weak.share() }
+    }
+
+    /// Returns a reader to the value, or `None` if the value is gone.
+    #[track_caller]
+    pub fn upgrade(&self) -> Option<RcnReader<T>> {
+        self.weak.upgrade().map(RcnReader::new)
+    }
+
+    /// Returns `true` if the value is gone.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.weak.is_none()
+    }
+
+    /// Returns `true` if the value is still alive.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.weak.is_some()
+    }
+}
+
+impl<T> Default for WeakReader<T> {
+    fn default() -> WeakReader<T> {
+        WeakReader::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for WeakReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(WeakReader)")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn readers_are_counted_apart() {
+        let mut log = Rcn::new(vec![1]);
+        let writer = log.share();
+        let reader = log.share_readonly();
+        let second = reader.share();
+        assert_eq!(log.strong_count(), 4);
+        assert_eq!(log.reader_count(), 2);
+        assert_eq!(reader.writer_count(), 2);
+
+        log.push(2);
+        assert_eq!(*second, [1, 2]);
+        drop((log, writer));
+        assert!(!reader.has_writers());
+        drop(second);
+        assert_eq!(reader.reader_count(), 1);
+
+        // A new box at the same address starts without readers.
+        drop(reader);
+        let fresh = Rcn::new(vec![3]);
+        assert_eq!(fresh.reader_count(), 0);
+    }
+
+    #[test]
+    fn weak_readers_upgrade_to_readers() {
+        let name = Rcn::new(String::from("sensor"));
+        let weak = name.share_readonly().downgrade();
+        assert!(weak.is_some());
+        assert_eq!(name.reader_count(), 0);
+
+        let reader = weak.upgrade().unwrap();
+        assert_eq!(&*reader, "sensor");
+        assert_eq!(name.reader_count(), 1);
+        assert!(reader.has_writers());
+        drop((name, reader));
+        assert!(weak.upgrade().is_none());
+        assert!(WeakReader::<u8>::new().upgrade().is_none());
+    }
+
+    #[test]
+    fn readers_of_aliases() {
+        let mut pair = Rcn::new((1, String::from("one")));
+        let name = Rcn::alias(&mut pair, |p| &mut p.1);
+        let reader = name.share_readonly();
+        assert_eq!(pair.reader_count(), 1);
+        assert_eq!(reader.writer_count(), 2);
+        drop((pair, name));
+        assert!(!reader.has_writers());
+        assert_eq!(&*reader, "one");
+    }
+}
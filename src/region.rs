@@ -0,0 +1,73 @@
+//! Memory regions whose boxes go back to an owner, such as a pool, instead of the global allocator.
+//!
+//! An owner claims the address range of the memory it hands out and marks the boxes it places there as
+//! owned. `RcnBox::deallocate` only looks up the range of owned boxes, and passes them to the owner of
+//! that range; every other box goes straight to the allocator.
+
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Takes back the memory of boxes inside the regions it claimed.
+pub(crate) trait Owner {
+    /// Called with the memory of a released box. Its value was already dropped or moved out.
+    unsafe fn release(&self, ptr: *mut u8, layout: Layout);
+}
+
+struct Region {
+    end: usize,
+    owner: Rc<dyn Owner>,
+}
+
+struct Regions {
+    by_start: BTreeMap<usize, Region>,
+}
+
+impl Drop for Regions {
+    fn drop(&mut self) {
+        if !self.by_start.is_empty() {
+            ORPHANED.with(|orphaned| orphaned.set(true));
+        }
+    }
+}
+
+thread_local! {
+    static REGIONS: RefCell<Regions> = const { RefCell::new(Regions { by_start: BTreeMap::new() }) };
+    /// Set when the table was destroyed on thread exit while regions were still claimed.
+    static ORPHANED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Claims `size` bytes starting at `start` for `owner`.
+pub(crate) fn claim(start: *mut u8, size: usize, owner: Rc<dyn Owner>) {
+    let region = Region { end: start as usize + size, owner };
+    REGIONS.with(|regions| regions.borrow_mut().by_start.insert(start as usize, region));
+}
+
+/// Gives up the region starting at `start`.
+pub(crate) fn unclaim(start: *mut u8) {
+    // The region may hold the last reference to its owner, which must not drop while borrowed.
+    let region = REGIONS.try_with(|regions| regions.borrow_mut().by_start.remove(&(start as usize)));
+    drop(region);
+}
+
+/// Hands the memory of a released box to the owner of the region containing it. Returns `false` if no
+/// region contains it, and the caller has to free it.
+pub(crate) unsafe fn release(ptr: *mut u8, layout: Layout) -> bool {
+    let address = ptr as usize;
+    let owner = REGIONS.try_with(|regions| {
+        regions.borrow().by_start.range(..=address).next_back()
+            .filter(|(_, region)| address < region.end)
+            .map(|(_, region)| region.owner.clone())
+    });
+    match owner {
+        Ok(Some(owner)) => {
+            owner.release(ptr, layout);
+            true
+        }
+        Ok(None) => false,
+        // The table is gone on thread exit. The box may lie inside a larger region that is no longer
+        // known, so it is leaked rather than freed; the thread is about to end anyway.
+        Err(_) => ORPHANED.with(Cell::get),
+    }
+}
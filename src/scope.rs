@@ -0,0 +1,294 @@
+//! Region allocation: boxes that are torn down together when their scope drops.
+
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+use std::thread;
+
+use crate::count;
+use crate::hooks;
+use crate::region::{self, Owner};
+use crate::{Rcn, RcnBox, WEAK_MAX};
+
+/// Size of the first chunk of a scope. Each further chunk doubles, up to `MAX_CHUNK`.
+const FIRST_CHUNK: usize = 4096;
+const MAX_CHUNK: usize = 1 << 20;
+
+/// An arena for `Rcn` boxes. [`alloc`](#method.alloc) places boxes in large chunks, and dropping the scope
+/// drops every value still alive, cycles included, and frees the chunks in bulk.
+///
+/// Handles must not outlive their scope. A handle that escapes is detected when the scope drops: the drop
+/// panics, the escaped handles read as none from then on, and the chunks are kept until the last of them
+/// is gone.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, RcnScope, Weakn};
+/// use std::cell::RefCell;
+///
+/// struct Node {
+///     next: RefCell<Option<Rcn<Node>>>,
+/// }
+///
+/// let scope = RcnScope::new();
+/// let a = scope.alloc(Node { next: RefCell::new(None) });
+/// let b = scope.alloc(Node { next: RefCell::new(Some(a.share())) });
+/// *a.next.borrow_mut() = Some(b.share());
+///
+/// drop((a, b));
+/// drop(scope); // frees the cycle
+/// ```
+pub struct RcnScope<'s> {
+    shared: Rc<Shared>,
+    phantom: PhantomData<Cell<&'s ()>>,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    ptr: *mut u8,
+    drop_value: unsafe fn(*mut u8),
+    deallocate: unsafe fn(*mut u8),
+}
+
+struct Shared {
+    chunks: RefCell<Vec<(*mut u8, Layout)>>,
+    /// Next free byte and end of the current chunk.
+    cursor: Cell<usize>,
+    end: Cell<usize>,
+    boxes: RefCell<Vec<Entry>>,
+    dying: Cell<bool>,
+    /// Boxes that still have escaped handles after the scope dropped.
+    pending: Cell<usize>,
+}
+
+unsafe fn drop_value<T>(ptr: *mut u8) {
+    hooks::drop_value(ptr as *mut RcnBox<T>);
+}
+
+unsafe fn deallocate<T>(ptr: *mut u8) {
+    RcnBox::deallocate(ptr as *mut RcnBox<T>);
+}
+
+impl Shared {
+    /// Returns memory for a box of `layout`, starting a new chunk if the current one is full.
+    fn bump(this: &Rc<Shared>, layout: Layout) -> *mut u8 {
+        let start = (this.cursor.get() + layout.align() - 1) & !(layout.align() - 1);
+        if this.cursor.get() != 0 && start + layout.size() <= this.end.get() {
+            this.cursor.set(start + layout.size());
+            return start as *mut u8;
+        }
+        let last = this.chunks.borrow().last().map_or(FIRST_CHUNK / 2, |&(_, chunk)| chunk.size());
+        let size = (last * 2).min(MAX_CHUNK).max(layout.size());
+        let chunk = Layout::from_size_align(size, layout.align().max(16)).expect("RcnScope chunk too large");
+        let mem = unsafe { alloc(chunk) };
+        if mem.is_null() {
+            handle_alloc_error(chunk);
+        }
+        region::claim(mem, size, this.clone());
+        this.chunks.borrow_mut().push((mem, chunk));
+        this.cursor.set(mem as usize + layout.size());
+        this.end.set(mem as usize + size);
+        mem
+    }
+
+    /// Drops every value still alive and returns the number of escaped handles.
+    fn teardown(&self) -> usize {
+        self.dying.set(true);
+        let len = self.boxes.borrow().len();
+        for i in 0..len {
+            let entry = self.boxes.borrow()[i];
+            let header = entry.ptr as *mut RcnBox<()>;
+            unsafe {
+                let strong = (*header).strong.get();
+                if strong == 0 {
+                    continue;
+                }
+                // Remaining strong handles become weak ones, so handles to boxes torn down earlier
+                // read as none. One more weak reference keeps the box while its value drops.
+                (*header).strong.set(0);
+                (*header).set_weak_count((*header).weak_count().saturating_add(strong).saturating_add(1).min(WEAK_MAX));
+                (entry.drop_value)(entry.ptr);
+                let weak = count::decrement((*header).weak_count(), WEAK_MAX, "weak");
+                (*header).set_weak_count(weak);
+                if weak == 0 {
+                    (entry.deallocate)(entry.ptr);
+                }
+            }
+        }
+        let boxes = self.boxes.borrow();
+        let escaped = boxes.iter().map(|entry| unsafe { (*(entry.ptr as *mut RcnBox<()>)).weak_count() });
+        let (pending, handles) = escaped.filter(|&weak| weak > 0)
+            .fold((0, 0), |(pending, handles), weak| (pending + 1, handles + weak));
+        self.pending.set(pending);
+        drop(boxes);
+        if pending == 0 {
+            self.free_chunks();
+        }
+        handles
+    }
+
+    fn free_chunks(&self) {
+        let chunks = self.chunks.replace(Vec::new());
+        for (mem, chunk) in chunks {
+            region::unclaim(mem);
+            unsafe { dealloc(mem, chunk) };
+        }
+    }
+}
+
+impl Owner for Shared {
+    unsafe fn release(&self, ptr: *mut u8, _layout: Layout) {
+        // The memory is only reclaimed with the whole scope. Clearing the counts marks the box as gone,
+        // also after `take`, which releases a box that still has its strong count.
+        let header = ptr as *mut RcnBox<()>;
+        (*header).strong.set(0);
+        (*header).weak.set(0);
+        if self.dying.get() && self.pending.get() > 0 {
+            self.pending.set(self.pending.get() - 1);
+            if self.pending.get() == 0 {
+                self.free_chunks();
+            }
+        }
+    }
+}
+
+impl<'s> RcnScope<'s> {
+    /// Constructs an empty scope. No memory is allocated until the first box.
+    pub fn new() -> RcnScope<'s> {
+        RcnScope {
+            shared: Rc::new(Shared {
+                chunks: RefCell::new(Vec::new()),
+                cursor: Cell::new(0),
+                end: Cell::new(0),
+                boxes: RefCell::new(Vec::new()),
+                dying: Cell::new(false),
+                pending: Cell::new(0),
+            }),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Constructs a new `Rcn<T>` whose box lives in this scope.
+    #[track_caller]
+    pub fn alloc<T: 's>(&self, value: T) -> Rcn<T> {
+        let mem = Shared::bump(&self.shared, Layout::new::<RcnBox<T>>());
+        self.shared.boxes.borrow_mut().push(Entry {
+            ptr: mem,
+            drop_value: drop_value::<T>,
+            deallocate: deallocate::<T>,
+        });
+        unsafe {
+            let ptr = mem as *mut RcnBox<T>;
+            ptr::write(ptr, RcnBox::owned(value));
+            RcnBox::register(ptr);
+            Rcn::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+
+    /// Returns the number of boxes allocated in this scope, including released ones.
+    pub fn len(&self) -> usize {
+        self.shared.boxes.borrow().len()
+    }
+
+    /// Returns `true` if no box was allocated in this scope.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'s> Drop for RcnScope<'s> {
+    fn drop(&mut self) {
+        let escaped = self.shared.teardown();
+        if escaped > 0 && !thread::panicking() {
+            panic!("RcnScope dropped while {} handles to its boxes are still alive; they now read as none", escaped);
+        }
+    }
+}
+
+impl<'s> Default for RcnScope<'s> {
+    fn default() -> RcnScope<'s> {
+        RcnScope::new()
+    }
+}
+
+impl<'s> fmt::Debug for RcnScope<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcnScope").field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Weakn;
+    use std::panic::{self, AssertUnwindSafe};
+
+    struct Node<'a> {
+        drops: &'a Cell<usize>,
+        next: RefCell<Option<Rcn<Node<'a>>>>,
+        back: RefCell<Weakn<Node<'a>>>,
+    }
+
+    impl<'a> Drop for Node<'a> {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn cycles_are_torn_down() {
+        let drops = Cell::new(0);
+        let scope = RcnScope::new();
+        let nodes: Vec<Rcn<Node>> = (0..100)
+            .map(|_| scope.alloc(Node { drops: &drops, next: RefCell::new(None), back: RefCell::new(Weakn::new()) }))
+            .collect();
+        for i in 0..nodes.len() {
+            let next = &nodes[(i + 1) % nodes.len()];
+            *nodes[i].next.borrow_mut() = Some(next.share());
+            *next.back.borrow_mut() = nodes[i].downgrade();
+        }
+        let mut single = scope.alloc(String::from("taken"));
+        assert_eq!(single.take(), Some(String::from("taken")));
+        assert_eq!(scope.len(), 101);
+
+        drop(nodes);
+        assert_eq!(drops.get(), 0);
+        drop(scope);
+        assert_eq!(drops.get(), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "RcnScope dropped while 1 handles to its boxes are still alive")]
+    fn escaped_handle_panics() {
+        let escaped;
+        {
+            let scope = RcnScope::new();
+            escaped = scope.alloc(5);
+        }
+        drop(escaped);
+    }
+
+    #[test]
+    fn escaped_handles_read_as_none() {
+        let mut weaks = Vec::new();
+        let mut strong = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let scope = RcnScope::new();
+            let a = scope.alloc(vec![1]);
+            weaks.push(a.downgrade());
+            weaks.push(a.downgrade());
+            drop(a);
+            let b = scope.alloc(vec![2]);
+            weaks.push(b.downgrade());
+            strong = Some(b);
+        }));
+        assert!(result.is_err());
+        assert!(strong.as_ref().unwrap().is_none());
+        assert!(weaks.iter().all(|w| w.upgrade().is_none()));
+    }
+}
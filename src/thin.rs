@@ -0,0 +1,473 @@
+//! `ThinRcn<T>`: a one-word reference-counting pointer to a slice or string.
+
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
+use std::slice;
+
+use crate::count;
+use crate::Rcn;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A dynamically sized type that [`ThinRcn`](struct.ThinRcn.html) can point to: `[T]` or `str`.
+pub trait ThinDst: sealed::Sealed {
+    #[doc(hidden)]
+    type Item;
+
+    #[doc(hidden)]
+    unsafe fn from_items(items: &[Self::Item]) -> &Self;
+}
+
+impl<T> sealed::Sealed for [T] {}
+
+impl<T> ThinDst for [T] {
+    type Item = T;
+
+    #[inline(always)]
+    unsafe fn from_items(items: &[T]) -> &[T] {
+        items
+    }
+}
+
+impl sealed::Sealed for str {}
+
+impl ThinDst for str {
+    type Item = u8;
+
+    #[inline(always)]
+    unsafe fn from_items(items: &[u8]) -> &str {
+        std::str::from_utf8_unchecked(items)
+    }
+}
+
+/// Header of a thin box. The items follow it in the same allocation.
+#[repr(C)]
+struct ThinHeader {
+    strong: Cell<usize>,
+    weak: Cell<usize>,
+    len: usize,
+}
+
+/// Returns the layout of a thin box of `len` items of type `I`, and the offset of the first item.
+fn layout<I>(len: usize) -> (Layout, usize) {
+    let (layout, offset) = Layout::array::<I>(len)
+        .and_then(|array| Layout::new::<ThinHeader>().extend(array))
+        .expect("ThinRcn too large");
+    (layout.pad_to_align(), offset)
+}
+
+/// A single-threaded reference-counting pointer to a `[T]` or `str`, one `usize` wide.
+///
+/// An `Rcn<[T]>` or `Rcn<str>` is a fat pointer: it carries the length next to the address. `ThinRcn` stores
+/// the length in the box header next to the strong and weak counts instead, so the handle itself is a single
+/// word and a none `ThinRcn` is a null pointer. Converting from and to `Rcn<[T]>`/`Rcn<str>` copies the items.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, ThinRcn};
+///
+/// let name: ThinRcn<str> = ThinRcn::from("statue");
+/// let shared = name.share();
+/// assert_eq!(&*shared, "statue");
+/// assert_eq!(std::mem::size_of::<ThinRcn<str>>(), std::mem::size_of::<usize>());
+///
+/// let fat: Rcn<str> = Rcn::from(name);
+/// assert_eq!(&*fat, "statue");
+/// ```
+pub struct ThinRcn<T: ?Sized + ThinDst> {
+    ptr: *mut ThinHeader,
+    phantom: PhantomData<T>,
+}
+
+/// A weak version of [`ThinRcn`](struct.ThinRcn.html).
+pub struct ThinWeakn<T: ?Sized + ThinDst> {
+    ptr: *mut ThinHeader,
+    phantom: PhantomData<T>,
+}
+
+impl<T: ?Sized + ThinDst> ThinRcn<T> {
+    /// Constructs a `ThinRcn<T>` with none value.
+    pub fn none() -> ThinRcn<T> {
+        ThinRcn { ptr: ptr::null_mut(), phantom: PhantomData }
+    }
+
+    /// Allocates a thin box holding clones of `items`.
+    fn from_items(items: &[T::Item]) -> ThinRcn<T> where T::Item: Clone {
+        unsafe {
+            let ptr = ThinRcn::<T>::allocate(items.len());
+            let data = ThinRcn::<T>::data(ptr);
+            for (i, item) in items.iter().enumerate() {
+                ptr::write(data.add(i), item.clone());
+            }
+            ThinRcn { ptr, phantom: PhantomData }
+        }
+    }
+
+    /// Allocates a thin box for `len` uninitialized items, with one strong and no weak references.
+    unsafe fn allocate(len: usize) -> *mut ThinHeader {
+        let (layout, _) = layout::<T::Item>(len);
+        let ptr = alloc(layout) as *mut ThinHeader;
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        ptr::write(ptr, ThinHeader { strong: Cell::new(1), weak: Cell::new(0), len });
+        #[cfg(feature = "leak-check")]
+        crate::debug::register_counts::<T, usize>(ptr, layout.size(), &(*ptr).strong, &(*ptr).weak);
+        ptr
+    }
+
+    #[inline]
+    unsafe fn data(ptr: *mut ThinHeader) -> *mut T::Item {
+        (ptr as *mut u8).add(layout::<T::Item>(0).1) as *mut T::Item
+    }
+
+    unsafe fn deallocate(ptr: *mut ThinHeader) {
+        #[cfg(feature = "leak-check")]
+        crate::debug::unregister(ptr);
+        dealloc(ptr as *mut u8, layout::<T::Item>((*ptr).len).0);
+    }
+
+    /// Gets the number of strong pointers to this value.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        if self.ptr.is_null() { 0 } else { unsafe { (*self.ptr).strong.get() } }
+    }
+
+    /// Gets the number of weak pointers to this value.
+    #[inline]
+    pub fn weak_count(&self) -> usize {
+        if self.ptr.is_null() { 0 } else { unsafe { (*self.ptr).weak.get() } }
+    }
+
+    /// Returns `true` if `weak_count == 0` and `strong_count == 1`.
+    #[inline]
+    pub fn is_unique(&self) -> bool {
+        self.weak_count() == 0 && self.strong_count() == 1
+    }
+
+    /// Returns `true` if the current pointer is `None`.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// Returns `true` if the current pointer is not `None`.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        !self.ptr.is_null()
+    }
+
+    /// Returns true if the two pointers point to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Creates another pointer to the same value, increasing the strong count.
+    pub fn share(&self) -> ThinRcn<T> {
+        if self.is_some() {
+            unsafe { (*self.ptr).strong.set(count::increment(self.strong_count(), usize::MAX)) };
+            ThinRcn { ptr: self.ptr, phantom: PhantomData }
+        } else {
+            panic!("share of ThinRcn with none value");
+        }
+    }
+
+    /// Creates a new [`ThinWeakn`](struct.ThinWeakn.html) pointer to this value.
+    pub fn downgrade(&self) -> ThinWeakn<T> {
+        if self.is_some() {
+            unsafe { (*self.ptr).weak.set(count::increment(self.weak_count(), usize::MAX)) };
+        }
+        ThinWeakn { ptr: self.ptr, phantom: PhantomData }
+    }
+}
+
+impl<T: ?Sized + ThinDst> Drop for ThinRcn<T> {
+    fn drop(&mut self) {
+        if self.is_some() {
+            unsafe {
+                let strong = count::decrement(self.strong_count(), usize::MAX, "strong");
+                (*self.ptr).strong.set(strong);
+                if strong == 0 {
+                    let weak = &(*self.ptr).weak;
+                    weak.set(count::increment(weak.get(), usize::MAX));
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ThinRcn::<T>::data(self.ptr), (*self.ptr).len));
+                    weak.set(count::decrement(weak.get(), usize::MAX, "weak"));
+                    if weak.get() == 0 {
+                        ThinRcn::<T>::deallocate(self.ptr);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: ?Sized + ThinDst> Deref for ThinRcn<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        if self.is_some() {
+            unsafe {
+                T::from_items(slice::from_raw_parts(ThinRcn::<T>::data(self.ptr), (*self.ptr).len))
+            }
+        } else {
+            panic!("deref of none rcn!");
+        }
+    }
+}
+
+impl<T: ?Sized + ThinDst> AsRef<T> for ThinRcn<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized + ThinDst> Borrow<T> for ThinRcn<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Clone> From<&[T]> for ThinRcn<[T]> {
+    fn from(v: &[T]) -> ThinRcn<[T]> {
+        ThinRcn::from_items(v)
+    }
+}
+
+impl<T> From<Vec<T>> for ThinRcn<[T]> {
+    fn from(mut v: Vec<T>) -> ThinRcn<[T]> {
+        unsafe {
+            let ptr = ThinRcn::<[T]>::allocate(v.len());
+            ptr::copy_nonoverlapping(v.as_ptr(), ThinRcn::<[T]>::data(ptr), v.len());
+            v.set_len(0);
+            ThinRcn { ptr, phantom: PhantomData }
+        }
+    }
+}
+
+impl From<&str> for ThinRcn<str> {
+    fn from(v: &str) -> ThinRcn<str> {
+        ThinRcn::from_items(v.as_bytes())
+    }
+}
+
+impl From<String> for ThinRcn<str> {
+    fn from(v: String) -> ThinRcn<str> {
+        ThinRcn::from_items(v.as_bytes())
+    }
+}
+
+impl<T: Clone> From<Rcn<[T]>> for ThinRcn<[T]> {
+    /// Copies the items of a fat `Rcn<[T]>` into a new thin box. A none `Rcn` becomes a none `ThinRcn`.
+    fn from(v: Rcn<[T]>) -> ThinRcn<[T]> {
+        if v.is_none() { ThinRcn::none() } else { ThinRcn::from_items(&v) }
+    }
+}
+
+impl From<Rcn<str>> for ThinRcn<str> {
+    /// Copies a fat `Rcn<str>` into a new thin box. A none `Rcn` becomes a none `ThinRcn`.
+    fn from(v: Rcn<str>) -> ThinRcn<str> {
+        if v.is_none() { ThinRcn::none() } else { ThinRcn::from_items(v.as_bytes()) }
+    }
+}
+
+impl<T: Clone> From<ThinRcn<[T]>> for Rcn<[T]> {
+    /// Copies the items of a `ThinRcn<[T]>` into a new fat `Rcn<[T]>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` is none, since an unsized `Rcn` has no none value.
+    fn from(v: ThinRcn<[T]>) -> Rcn<[T]> {
+        Rcn::from(&*v)
+    }
+}
+
+impl From<ThinRcn<str>> for Rcn<str> {
+    /// Copies a `ThinRcn<str>` into a new fat `Rcn<str>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` is none, since an unsized `Rcn` has no none value.
+    fn from(v: ThinRcn<str>) -> Rcn<str> {
+        Rcn::from(&*v)
+    }
+}
+
+impl<T: ?Sized + ThinDst> Default for ThinRcn<T> {
+    fn default() -> ThinRcn<T> {
+        ThinRcn::none()
+    }
+}
+
+impl<T: ?Sized + ThinDst + fmt::Display> fmt::Display for ThinRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + ThinDst + fmt::Debug> fmt::Debug for ThinRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + ThinDst + PartialEq> PartialEq for ThinRcn<T> {
+    fn eq(&self, other: &ThinRcn<T>) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + ThinDst + Eq> Eq for ThinRcn<T> {}
+
+impl<T: ?Sized + ThinDst + PartialOrd> PartialOrd for ThinRcn<T> {
+    fn partial_cmp(&self, other: &ThinRcn<T>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + ThinDst + Ord> Ord for ThinRcn<T> {
+    fn cmp(&self, other: &ThinRcn<T>) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + ThinDst + Hash> Hash for ThinRcn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized + ThinDst> ThinWeakn<T> {
+    /// Constructs a `ThinWeakn<T>` with none value.
+    pub fn new() -> ThinWeakn<T> {
+        ThinWeakn { ptr: ptr::null_mut(), phantom: PhantomData }
+    }
+
+    /// Returns `true` if the value was dropped or the pointer is none.
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_null() || unsafe { (*self.ptr).strong.get() } == 0
+    }
+
+    /// Returns `true` if the value is still alive.
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        !self.is_none()
+    }
+
+    /// Attempts to upgrade to a [`ThinRcn`](struct.ThinRcn.html), returning `None` if the value was dropped.
+    pub fn upgrade(&self) -> Option<ThinRcn<T>> {
+        if self.is_none() {
+            return None;
+        }
+        unsafe { (*self.ptr).strong.set(count::increment((*self.ptr).strong.get(), usize::MAX)) };
+        Some(ThinRcn { ptr: self.ptr, phantom: PhantomData })
+    }
+}
+
+impl<T: ?Sized + ThinDst> Default for ThinWeakn<T> {
+    fn default() -> ThinWeakn<T> {
+        ThinWeakn::new()
+    }
+}
+
+impl<T: ?Sized + ThinDst> Drop for ThinWeakn<T> {
+    fn drop(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+        unsafe {
+            let weak = count::decrement((*self.ptr).weak.get(), usize::MAX, "weak");
+            (*self.ptr).weak.set(weak);
+            if weak == 0 && (*self.ptr).strong.get() == 0 {
+                ThinRcn::<T>::deallocate(self.ptr);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized + ThinDst> fmt::Debug for ThinWeakn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(ThinWeakn)")
+    }
+}
+
+const _: () = assert!(mem::size_of::<ThinRcn<str>>() == mem::size_of::<usize>());
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn saturated_weak_count_survives_the_last_drop() {
+        let a: ThinRcn<str> = ThinRcn::from("immortal");
+        let weak = a.downgrade();
+        unsafe { (*a.ptr).weak.set(usize::MAX) };
+        drop(a);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(unsafe { (*weak.ptr).weak.get() }, usize::MAX);
+    }
+
+    #[test]
+    fn one_word() {
+        assert_eq!(mem::size_of::<ThinRcn<[u64]>>(), mem::size_of::<usize>());
+        assert!(mem::size_of::<Rcn<[u64]>>() >= 2 * mem::size_of::<usize>());
+        let none: ThinRcn<str> = ThinRcn::none();
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn slices_and_strings() {
+        let items: ThinRcn<[String]> = ThinRcn::from(vec![String::from("a"), String::from("b")]);
+        let shared = items.share();
+        assert_eq!(items.strong_count(), 2);
+        assert_eq!(shared.len(), 2);
+        assert_eq!(shared[1], "b");
+        drop(items);
+
+        let weak = shared.downgrade();
+        assert!(weak.upgrade().is_some());
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+
+        let empty: ThinRcn<[u8]> = ThinRcn::from(&[][..]);
+        assert!(empty.is_empty());
+
+        let mut table = HashSet::new();
+        table.insert(ThinRcn::<str>::from("x"));
+        assert!(table.contains("x"));
+    }
+
+    #[test]
+    fn converts_to_and_from_rcn() {
+        let fat: Rcn<[i32]> = Rcn::from(vec![1, 2, 3]);
+        let thin = ThinRcn::from(fat);
+        assert_eq!(&*thin, &[1, 2, 3]);
+        let back: Rcn<[i32]> = Rcn::from(thin);
+        assert_eq!(&*back, &[1, 2, 3]);
+
+        let s: Rcn<str> = Rcn::from(String::from("over aligned"));
+        let thin = ThinRcn::from(s);
+        assert_eq!(&*Rcn::<str>::from(thin), "over aligned");
+
+        #[derive(Clone, Debug, PartialEq)]
+        #[repr(align(32))]
+        struct Wide(u8);
+        let wide = ThinRcn::from(&[Wide(1), Wide(2)][..]);
+        assert_eq!(&*Rcn::<[Wide]>::from(wide), &[Wide(1), Wide(2)]);
+    }
+}
@@ -0,0 +1,194 @@
+//! All-or-nothing updates: snapshots taken before a modification and restored if it fails.
+
+/// Saves and restores the state of a value for [`transaction`](fn.transaction.html) and
+/// [`Rcn::transaction`](struct.Rcn.html#method.transaction).
+///
+/// Every `Clone` type is a `Snapshot` that saves a clone of itself. Types that are not `Clone`, or that can
+/// save less than a full copy, implement it themselves.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, Snapshot};
+///
+/// /// A log that only grows, so a snapshot is just its length.
+/// struct Log {
+///     lines: Vec<String>,
+/// }
+///
+/// impl Snapshot for Log {
+///     type Saved = usize;
+///
+///     fn snapshot(&self) -> usize {
+///         self.lines.len()
+///     }
+///
+///     fn restore(&mut self, len: usize) {
+///         self.lines.truncate(len);
+///     }
+/// }
+///
+/// let mut log = Rcn::new(Log { lines: vec![String::from("boot")] });
+/// let _reader = log.share();
+/// let result: Result<(), &str> = log.transaction(|log| {
+///     log.lines.push(String::from("half"));
+///     Err("disk full")
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(log.lines, ["boot"]);
+/// ```
+pub trait Snapshot {
+    /// The saved state.
+    type Saved;
+
+    /// Saves the current state.
+    fn snapshot(&self) -> Self::Saved;
+
+    /// Puts back a state saved by [`snapshot`](#tymethod.snapshot).
+    fn restore(&mut self, saved: Self::Saved);
+}
+
+impl<T: Clone> Snapshot for T {
+    type Saved = T;
+
+    fn snapshot(&self) -> T {
+        self.clone()
+    }
+
+    fn restore(&mut self, saved: T) {
+        *self = saved;
+    }
+}
+
+/// Restores the snapshot unless the transaction committed, also when it unwinds.
+struct Rollback<'a, T: Snapshot + ?Sized> {
+    value: &'a mut T,
+    saved: Option<T::Saved>,
+}
+
+impl<'a, T: Snapshot + ?Sized> Drop for Rollback<'a, T> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            self.value.restore(saved);
+        }
+    }
+}
+
+/// Runs `f` on `value` and keeps its changes only if it returns `Ok`. If it returns `Err` or panics,
+/// `value` is restored from a [`Snapshot`](trait.Snapshot.html) taken beforehand.
+///
+/// Transactions nest: a transaction on a part of the value, started inside `f`, rolls back on its own
+/// without failing the outer one.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{transaction, Rcn};
+///
+/// let mut order = Rcn::new((vec!["tea"], 0));
+/// let placed: Result<(), ()> = order.transaction(|(items, total)| {
+///     items.push("cake");
+///     *total += 1;
+///     // An optional extra that may fail on its own.
+///     let _ = transaction(items, |items| {
+///         items.push("cream");
+///         Err::<(), ()>(())
+///     });
+///     Ok(())
+/// });
+/// assert!(placed.is_ok());
+/// assert_eq!(*order, (vec!["tea", "cake"], 1));
+/// ```
+pub fn transaction<T, R, E, F>(value: &mut T, f: F) -> Result<R, E>
+where
+    T: Snapshot + ?Sized,
+    F: FnOnce(&mut T) -> Result<R, E>,
+{
+    let saved = value.snapshot();
+    let mut rollback = Rollback { value, saved: Some(saved) };
+    let result = f(&mut *rollback.value);
+    if result.is_ok() {
+        // Committed: the snapshot is dropped instead of restored.
+        drop(rollback.saved.take());
+    }
+    drop(rollback);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Rcn;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Account {
+        balance: i64,
+        history: Vec<i64>,
+    }
+
+    fn deposit(account: &mut Account, amount: i64) -> Result<(), &'static str> {
+        account.history.push(amount);
+        account.balance += amount;
+        if account.balance < 0 {
+            return Err("overdrawn");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn shared_values_roll_back() {
+        let mut account = Rcn::new(Account { balance: 10, history: Vec::new() });
+        let observer = account.share();
+        assert_eq!(account.transaction(|a| deposit(a, 5)), Ok(()));
+        assert_eq!(account.transaction(|a| deposit(a, -50)), Err("overdrawn"));
+        assert_eq!(*observer, Account { balance: 15, history: vec![5] });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| account.transaction(|a| {
+            a.balance = 0;
+            deposit(a, i64::MAX).and_then(|_| deposit(a, 1))
+        })));
+        assert!(result.is_err());
+        assert_eq!(observer.balance, 15);
+        assert!(!observer.is_poisoned());
+    }
+
+    #[test]
+    fn nested_transactions() {
+        let mut account = Rcn::new(Account { balance: 0, history: Vec::new() });
+        let observer = account.share();
+        let outer: Result<(), ()> = account.transaction(|a| {
+            a.balance = 1;
+            assert_eq!(transaction(a, |a| deposit(a, -5)), Err("overdrawn"));
+            assert_eq!(transaction(&mut a.history, |h| { h.push(2); Ok::<_, ()>(()) }), Ok(()));
+            Ok(())
+        });
+        assert_eq!(outer, Ok(()));
+        assert_eq!(*observer, Account { balance: 1, history: vec![2] });
+
+        let outer: Result<(), ()> = account.transaction(|a| {
+            let _ = transaction(a, |a| deposit(a, 4));
+            Err(())
+        });
+        assert_eq!(outer, Err(()));
+        assert_eq!(observer.balance, 1);
+    }
+
+    #[test]
+    fn unique_values_roll_back() {
+        let mut unique = Rcn::new(Account { balance: 1, history: Vec::new() });
+        assert!(unique.is_unique());
+        assert_eq!(unique.transaction(|a| deposit(a, -5)), Err("overdrawn"));
+        assert_eq!(*unique, Account { balance: 1, history: Vec::new() });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unique.transaction(|a| -> Result<(), ()> {
+            a.balance = 0;
+            panic!("interrupted");
+        })));
+        assert!(result.is_err());
+        assert_eq!(unique.balance, 1);
+        assert!(!unique.is_poisoned());
+    }
+}
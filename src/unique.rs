@@ -0,0 +1,226 @@
+//! `UniqueRcn<T>`: an unshared `Rcn` box, for values that are built before they are published.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+use crate::count;
+use crate::drops;
+use crate::hashed;
+use crate::hooks;
+use crate::{Rcn, RcnBox, Weakn, HASHED, WEAK_MAX};
+
+/// The only handle to an `Rcn` box, which can be mutated freely and published as an `Rcn<T>` with
+/// [`into_shared`](#method.into_shared) at no cost.
+///
+/// Until it is published the box has no strong references: the `UniqueRcn` holds a weak one instead, so
+/// the `Weakn`s made by [`downgrade`](#method.downgrade) read as none and cannot upgrade until then.
+///
+/// # Examples
+///
+/// ```
+/// extern crate rcn;
+/// use rcn::{Rcn, UniqueRcn};
+///
+/// let mut list = UniqueRcn::new(Vec::new());
+/// let weak = UniqueRcn::downgrade(&list);
+/// list.push(1);
+/// list.push(2);
+/// assert!(weak.upgrade().is_none());
+///
+/// let shared: Rcn<Vec<i32>> = UniqueRcn::into_shared(list);
+/// assert_eq!(*weak.upgrade().unwrap(), [1, 2]);
+/// # drop(shared);
+/// ```
+pub struct UniqueRcn<T: ?Sized> {
+    ptr: NonNull<RcnBox<T>>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> UniqueRcn<T> {
+    /// Constructs a new `UniqueRcn<T>`.
+    pub fn new(value: T) -> UniqueRcn<T> {
+        let ptr = RcnBox::allocate(value);
+        unsafe {
+            ptr.as_ref().strong.set(0);
+            ptr.as_ref().set_weak_count(1);
+        }
+        UniqueRcn { ptr, phantom: PhantomData }
+    }
+
+    /// Moves the value out, releasing the box.
+    pub fn into_inner(this: Self) -> T {
+        let ptr = this.ptr;
+        mem::forget(this);
+        unsafe {
+            let value = ptr::read(&ptr.as_ref().value);
+            if ptr.as_ref().weak_count() > 1 {
+                hooks::value_moved(ptr.as_ptr());
+            }
+            UniqueRcn::release_weak(ptr);
+            value
+        }
+    }
+}
+
+impl<T: ?Sized> UniqueRcn<T> {
+    /// Takes over the box of `rcn`, which must be unique.
+    pub(crate) fn from_unique(mut rcn: Rcn<T>) -> UniqueRcn<T> {
+        debug_assert!(rcn.is_unique());
+        rcn.untrack();
+        let ptr = rcn.ptr;
+        mem::forget(rcn);
+        unsafe {
+            ptr.as_ref().strong.set(0);
+            ptr.as_ref().set_weak_count(1);
+        }
+        UniqueRcn { ptr, phantom: PhantomData }
+    }
+
+    /// Publishes the value, turning this handle into the first strong `Rcn<T>` to it. `Weakn`s made
+    /// before can upgrade from now on.
+    pub fn into_shared(this: Self) -> Rcn<T> {
+        let ptr = this.ptr;
+        mem::forget(this);
+        unsafe {
+            ptr.as_ref().strong.set(1);
+            ptr.as_ref().set_weak_count(count::decrement(ptr.as_ref().weak_count(), WEAK_MAX, "weak"));
+        }
+        Rcn::from_inner(ptr)
+    }
+
+    /// Creates a weak handle to the value, which cannot upgrade until the value is published.
+    #[track_caller]
+    pub fn downgrade(this: &Self) -> Weakn<T> {
+        let header = unsafe { this.ptr.as_ref() };
+        header.set_weak_count(count::increment(header.weak_count(), WEAK_MAX));
+        Weakn::from_inner(this.ptr)
+    }
+
+    /// Gets the number of `Weakn` pointers to this value.
+    #[inline]
+    pub fn weak_count(this: &Self) -> usize {
+        unsafe { this.ptr.as_ref().weak_count() - 1 }
+    }
+
+    /// Gives up the weak reference held by an unpublished handle, freeing the box if it was the last.
+    unsafe fn release_weak(ptr: NonNull<RcnBox<T>>) {
+        let weak = count::decrement(ptr.as_ref().weak_count(), WEAK_MAX, "weak");
+        ptr.as_ref().set_weak_count(weak);
+        if weak == 0 {
+            RcnBox::deallocate(ptr.as_ptr());
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for UniqueRcn<T> {
+    fn drop(&mut self) {
+        // `release` holds a weak reference of its own while the value drops, so the one of this handle is
+        // given up first; otherwise it would look like a `Weakn` left behind.
+        unsafe {
+            let header = self.ptr.as_ref();
+            header.set_weak_count(count::decrement(header.weak_count(), WEAK_MAX, "weak"));
+            drops::release(self.ptr);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for UniqueRcn<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &self.ptr.as_ref().value }
+    }
+}
+
+impl<T: ?Sized> DerefMut for UniqueRcn<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        if unsafe { self.ptr.as_ref().has_flag(HASHED) } {
+            hashed::forget(self.ptr.as_ptr() as *mut () as *mut RcnBox<()>);
+        }
+        unsafe { &mut self.ptr.as_mut().value }
+    }
+}
+
+impl<T: Default> Default for UniqueRcn<T> {
+    fn default() -> UniqueRcn<T> {
+        UniqueRcn::new(T::default())
+    }
+}
+
+impl<T> From<T> for UniqueRcn<T> {
+    fn from(value: T) -> UniqueRcn<T> {
+        UniqueRcn::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for UniqueRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for UniqueRcn<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unpublished_values_cannot_upgrade() {
+        let mut name = UniqueRcn::new(String::from("draft"));
+        let weak = UniqueRcn::downgrade(&name);
+        name.push_str(" 2");
+        assert_eq!(UniqueRcn::weak_count(&name), 1);
+        assert!(weak.is_none());
+
+        let shared = UniqueRcn::into_shared(name);
+        assert_eq!((shared.strong_count(), shared.weak_count()), (1, 1));
+        assert_eq!(*weak.upgrade().unwrap(), "draft 2");
+
+        let back = Rcn::try_into_unique(shared).unwrap_err();
+        drop(weak);
+        let unique = Rcn::try_into_unique(back).unwrap();
+        assert_eq!(UniqueRcn::into_inner(unique), "draft 2");
+    }
+
+    #[test]
+    fn dropping_unpublished_values() {
+        let unique = UniqueRcn::new(vec![1]);
+        let weak = UniqueRcn::downgrade(&unique);
+        drop(unique);
+        assert!(weak.upgrade().is_none());
+        drop(weak);
+
+        let value = UniqueRcn::new(5);
+        let weak = UniqueRcn::downgrade(&value);
+        assert_eq!(UniqueRcn::into_inner(value), 5);
+        assert!(weak.is_none());
+    }
+
+    #[test]
+    fn orphan_hooks_only_see_weakns() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let orphaned = Rc::new(Cell::new(0));
+        for weak in [false, true] {
+            let rcn = Rcn::new(7);
+            let count = orphaned.clone();
+            rcn.on_orphan(move || count.set(count.get() + 1));
+            let unique = Rcn::try_into_unique(rcn).unwrap();
+            let weak = weak.then(|| UniqueRcn::downgrade(&unique));
+            drop(unique);
+            drop(weak);
+        }
+        assert_eq!(orphaned.get(), 1);
+    }
+}
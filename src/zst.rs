@@ -0,0 +1,194 @@
+//! Shared per-thread headers for zero-sized payloads.
+//!
+//! A zero-sized value carries no data, so every `Rcn` of such a type on a thread can share one header:
+//! only the counts matter. The header of each type is allocated the first time it is needed and cached
+//! when its last handle goes away, so creating and dropping marker handles does not touch the allocator
+//! again. Cached headers are released on thread exit.
+//!
+//! Sharing is opt-in through `Rcn::new_zst`; `Rcn::new` always allocates a box of its own. Since the header
+//! is shared, so is the state kept for its address: freezing, poisoning and hooks set through one handle
+//! apply to every handle of the type until the header goes idle again.
+
+use std::alloc::{dealloc, Layout};
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use crate::count;
+use crate::RcnBox;
+
+struct Header {
+    ptr: *mut RcnBox<()>,
+    layout: Layout,
+}
+
+struct Headers {
+    by_type: HashMap<TypeId, Header>,
+    /// Addresses of the headers in `by_type`, so a box is recognized as a header without a scan.
+    addresses: HashSet<usize>,
+}
+
+impl Drop for Headers {
+    fn drop(&mut self) {
+        for (_, header) in self.by_type.drain() {
+            // Headers still referenced by handles stored in other thread locals are left to those
+            // handles, which release them like any other box once this table is gone.
+            unsafe {
+                if (*header.ptr).strong.get() == 0 && (*header.ptr).weak_count() == 0 {
+                    dealloc(header.ptr as *mut u8, header.layout);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    static HEADERS: RefCell<Headers> = RefCell::new(Headers { by_type: HashMap::new(), addresses: HashSet::new() });
+}
+
+/// Returns the header of `T` on this thread with one more strong reference, holding `value` if the
+/// header had no value yet. Otherwise `value` is dropped, since the header already holds an equal one.
+pub(crate) fn header<T: 'static>(value: T) -> NonNull<RcnBox<T>> {
+    debug_assert!(mem::size_of::<T>() == 0);
+    let shared = HEADERS.try_with(|headers| {
+        let mut headers = headers.borrow_mut();
+        let cached = headers.by_type.get(&TypeId::of::<T>()).map(|header| header.ptr as *mut RcnBox<T>);
+        if let Some(ptr) = cached {
+            unsafe {
+                let strong = (*ptr).strong.get();
+                if strong > 0 {
+                    (*ptr).strong.set(count::increment(strong, usize::MAX));
+                    return Some(NonNull::new_unchecked(ptr));
+                }
+                if (*ptr).weak_count() == 0 {
+                    return Some(NonNull::new_unchecked(ptr));
+                }
+            }
+            // The value is gone but weak handles remain. They must not see a new value, so this
+            // header is left to them and freed by the last one.
+            if let Some(header) = headers.by_type.remove(&TypeId::of::<T>()) {
+                headers.addresses.remove(&(header.ptr as usize));
+            }
+        }
+        None
+    });
+    match shared {
+        Ok(Some(ptr)) => unsafe {
+            let header = ptr.as_ptr();
+            if (*header).strong.get() == 0 {
+                // An idle cached header: it holds no value and no handles, so it becomes a new box.
+                ptr::write(&mut (*header).value, value);
+                (*header).strong.set(1);
+                RcnBox::register(header);
+            } else {
+                drop(value);
+            }
+            ptr
+        },
+        Ok(None) => {
+            let ptr = RcnBox::allocate(value);
+            let header = Header { ptr: ptr.as_ptr() as *mut RcnBox<()>, layout: Layout::new::<RcnBox<T>>() };
+            // The table is only missing during thread exit; the box is then released like any other.
+            let _ = HEADERS.try_with(|headers| {
+                let mut headers = headers.borrow_mut();
+                headers.addresses.insert(header.ptr as usize);
+                headers.by_type.insert(TypeId::of::<T>(), header);
+            });
+            ptr
+        }
+        Err(_) => RcnBox::allocate(value),
+    }
+}
+
+/// Called when the box at `ptr` is about to be freed. Returns `true` if it is a cached header, which is
+/// then kept for the next handle of its type instead.
+pub(crate) fn retain(ptr: *mut RcnBox<()>) -> bool {
+    let cached = HEADERS.try_with(|headers| {
+        headers.borrow().addresses.contains(&(ptr as usize))
+    }).unwrap_or(false);
+    if cached {
+        // `take` moves the value out without dropping the strong count.
+        unsafe {
+            (*ptr).strong.set(0);
+            (*ptr).weak.set(0);
+        }
+    }
+    cached
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Rcn;
+    use std::cell::Cell;
+
+    #[derive(Debug, PartialEq)]
+    struct Marker;
+
+    #[test]
+    fn handles_share_one_header() {
+        let a = Rcn::new_zst(Marker);
+        let b = Rcn::new_zst(Marker);
+        assert!(Rcn::ptr_eq(&a, &b));
+        assert_eq!(a.strong_count(), 2);
+        drop(b);
+        assert!(a.is_unique());
+        let address = &*a as *const Marker;
+        drop(a);
+
+        let c = Rcn::new_zst(Marker);
+        assert_eq!(&*c as *const Marker, address);
+        assert_eq!(c.strong_count(), 1);
+        assert_eq!(*c, Marker);
+    }
+
+    #[test]
+    fn take_and_weak_handles() {
+        let mut a = Rcn::new_zst(());
+        assert_eq!(a.take(), Some(()));
+        let b = Rcn::new_zst(());
+        assert_eq!(b.strong_count(), 1);
+
+        let w = b.downgrade();
+        drop(b);
+        assert!(w.upgrade().is_none());
+        let c = Rcn::new_zst(());
+        assert!(w.upgrade().is_none());
+        assert!(c.is_unique());
+    }
+
+    #[test]
+    fn shared_state_lasts_until_the_header_is_idle() {
+        let a = Rcn::new_zst(Marker);
+        let b = Rcn::new_zst(Marker);
+        a.freeze();
+        assert!(b.is_frozen());
+        assert!(!Rcn::ptr_eq(&Rcn::new(Marker), &a));
+        drop((a, b));
+        assert!(!Rcn::new_zst(Marker).is_frozen());
+    }
+
+    thread_local! {
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Token;
+
+    impl Drop for Token {
+        fn drop(&mut self) {
+            DROPS.with(|drops| drops.set(drops.get() + 1));
+        }
+    }
+
+    #[test]
+    fn values_drop_once_per_header() {
+        let a = Rcn::new_zst(Token);
+        let b = Rcn::new_zst(Token);
+        assert_eq!(DROPS.with(Cell::get), 1);
+        drop(a);
+        assert_eq!(DROPS.with(Cell::get), 1);
+        drop(b);
+        assert_eq!(DROPS.with(Cell::get), 2);
+    }
+}